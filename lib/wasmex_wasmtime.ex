@@ -30,6 +30,9 @@ defmodule WasmexWasmtime do
   """
   use GenServer
 
+  # process dictionary key of the state of the callback running in the process
+  @callback_state :wasmex_wasmtime_callback_state
+
   # Client
 
   @doc """
@@ -61,6 +64,18 @@ defmodule WasmexWasmtime do
   1. the function to be executed: `fn (_context, a, b, c) -> a + b end`
 
   The first param the function receives is always the call context (a Map containing e.g. the instances memory).
+  The context contains the calling instance's `:memory` (its memory exported as `"memory"`, or
+  else its first exported memory, `nil` for instances without exported memory) and all of its
+  exported `:memories`, `:globals` (see `WasmexWasmtime.Global`), `:tables` (see
+  `WasmexWasmtime.Table`), and `:functions` (see `WasmexWasmtime.Function`) as maps by export
+  name, and the `:instance` the function was called from. Use them together with the `:caller`
+  of the context, e.g. to read a guest global:
+
+      fn %{caller: caller, globals: %{"heap_base" => heap_base}}, size ->
+        WasmexWasmtime.Global.get(caller, heap_base) + size
+      end
+
+  Exported functions can be called from within a callback, see `call_function_from_callback/3`.
   All other params are regular parameters as specified by the parameter type list.
 
   Valid parameter/return types are:
@@ -72,6 +87,88 @@ defmodule WasmexWasmtime do
 
   The return type must always be one value.
 
+  An imported function blocks the WebAssembly execution until its callback returns. Give a
+  `callback_timeout` in milliseconds to trap callbacks taking longer, the call then returns
+  `{:error, :callback_timeout}`:
+
+      {:ok, instance } = WasmexWasmtime.start_link(%{module: module, imports: imports, callback_timeout: 5_000})
+
+  Should the GenServer exit before a callback returned, the call returns
+  `{:error, :callback_abandoned}`.
+
+  Callbacks are invoked by the GenServer by default, one at a time. To serve them in other
+  processes, e.g. a pool of workers, give a pid or registered name as `callback_pid` for all
+  imported functions, or as fifth element of the tuple of a single imported function:
+
+      imports = %{
+        env: %{
+          sum3: {:fn, [:i32, :i32, :i32], [:i32], fn (_context, a, b, c) -> a + b + c end},
+          fetch: {:fn, [:i32], [:i32], &fetch/2, :fetch_worker}
+        }
+      }
+      {:ok, instance } = WasmexWasmtime.start_link(%{module: module, imports: imports, callback_pid: worker})
+
+  These processes run the callbacks with `handle_callback/3`.
+
+  When a callback raises, throws, or exits, the WebAssembly call traps and returns
+  `{:error, {:callback_error, %{kind: kind, message: message, stacktrace: stacktrace}}}`,
+  with the `kind` of the failure (`:error`, `:throw`, or `:exit`), the formatted `message`,
+  and the formatted `stacktrace` of the callback.
+
+  Callbacks abort the WebAssembly call on purpose by returning `{:trap, reason}` with a
+  string reason or `{:exit, code}` with an integer exit code. The call then returns
+  `{:error, {:trap, reason}}` or `{:error, {:exit, code}}`:
+
+      fn _context, path_ptr, path_len ->
+        if allowed?(path_ptr, path_len), do: 0, else: {:trap, "permission denied"}
+      end
+
+  Besides functions, globals can be imported with a tuple of `{:global, type, value, mutability}`:
+
+      imports = %{
+        env: %{
+          max_connections: {:global, :i32, 42, :const},
+          counter: {:global, :i64, 0, :var}
+        }
+      }
+
+  The type is one of the parameter types above and mutability is either `:const` or `:var`.
+
+  Memories are imported with `{:memory, memory_type}`, the memory type being a map of
+  `:minimum` and `:maximum` size in pages and whether the memory is `:shared` (shared
+  memories need the `wasm_threads` engine option and a maximum). Alternatively, give an
+  existing `WasmexWasmtime.Memory` created with `WasmexWasmtime.Memory.new/2`:
+
+      imports = %{
+        env: %{
+          memory: {:memory, %{minimum: 1, maximum: 16}}
+        }
+      }
+
+  Tables are imported with `{:table, table_type}`, the table type being a map of `:minimum`
+  and `:maximum` number of elements and the element `:type` (`:func_ref` or `:extern_ref`).
+  Alternatively, give an existing `WasmexWasmtime.Table` created with `WasmexWasmtime.Table.new/2`:
+
+      imports = %{
+        env: %{
+          __indirect_function_table: {:table, %{minimum: 1, maximum: nil, type: :func_ref}}
+        }
+      }
+
+  ### Engine Configuration
+
+  When starting from `bytes`, the module is compiled and run with the given `engine`
+  (see `WasmexWasmtime.Engine` and `WasmexWasmtime.EngineConfig`):
+
+      {:ok, engine} = WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{wasm_multi_memory: true})
+      {:ok, instance } = WasmexWasmtime.start_link(%{bytes: bytes, engine: engine})
+
+  The resources a WebAssembly instance may use can be restricted with `store_limits`
+  (see `WasmexWasmtime.StoreLimits`):
+
+      limits = %WasmexWasmtime.StoreLimits{memory_size: 10 * 65_536}
+      {:ok, instance } = WasmexWasmtime.start_link(%{bytes: bytes, store_limits: limits})
+
   ### WASI
 
   Optionally, modules can be run with WebAssembly System Interface (WASI) support.
@@ -130,19 +227,23 @@ defmodule WasmexWasmtime do
     end
   end
 
-  def start_link(%{store: store, module: module, imports: imports}) when is_map(imports) do
+  def start_link(%{store: store, module: module, imports: imports} = opts) when is_map(imports) do
     GenServer.start_link(__MODULE__, %{
       store: store,
       module: module,
-      imports: stringify_keys(imports)
+      imports: stringify_keys(imports),
+      callback_timeout: Map.get(opts, :callback_timeout),
+      callback_pid: Map.get(opts, :callback_pid)
     })
   end
 
   defp build_store(opts) do
+    store_opts = [engine: Map.get(opts, :engine), limits: Map.get(opts, :store_limits)]
+
     if Map.has_key?(opts, :wasi) do
-      WasmexWasmtime.Store.new_wasi(stringify_keys(opts[:wasi]))
+      WasmexWasmtime.Store.new_wasi(stringify_keys(opts[:wasi]), store_opts)
     else
-      WasmexWasmtime.Store.new()
+      WasmexWasmtime.Store.new(store_opts)
     end
   end
 
@@ -222,18 +323,98 @@ defmodule WasmexWasmtime do
   ```elixir
   {:ok, [pointer]} = WasmexWasmtime.call_function(instance, "string", [], 10000)
   ```
+
+  If the engine is configured with `epoch_interruption: true` (see `WasmexWasmtime.EngineConfig`),
+  the WebAssembly call is interrupted once the timeout is reached, instead of keeping the
+  instance busy after the caller stopped waiting.
   """
   def call_function(pid, name, params, timeout \\ 5000) do
-    GenServer.call(pid, {:call_function, stringify(name), params}, timeout)
+    GenServer.call(pid, {:call_function, stringify(name), params, timeout}, timeout)
+  end
+
+  @doc """
+  Calls an exported function from within an imported function callback.
+
+  The call runs nested in the paused WebAssembly call which invoked the callback, on the same
+  instance. E.g. to allocate a buffer in guest memory and return a pointer to it:
+
+      imports = %{
+        env: %{
+          read_file:
+            {:fn, [:i32], [:i32],
+             fn context, size ->
+               {:ok, [pointer]} = WasmexWasmtime.call_function_from_callback(context, :malloc, [size])
+               WasmexWasmtime.Memory.write_binary(context.caller, context.memory, pointer, data)
+               pointer
+             end}
+        }
+      }
+
+  Imported functions called by the nested call are invoked while waiting for its result.
+  Fails with an error once the callback has returned, or if the callback was handled without
+  an instance (see `handle_callback/3`). Returns `{:error, :timeout}` if the nested call did
+  not return within the `callback_timeout` of the instance.
+  """
+  def call_function_from_callback(%{instance: nil}, _name, _params) do
+    {:error, "the callback was handled without an instance, see handle_callback/3"}
+  end
+
+  def call_function_from_callback(
+        %{caller: caller, instance: instance} = context,
+        name,
+        params
+      ) do
+    ref = make_ref()
+
+    case WasmexWasmtime.Instance.call_exported_function(
+           caller,
+           instance,
+           stringify(name),
+           params,
+           ref
+         ) do
+      :ok -> await_nested_call(ref, Process.get(@callback_state), context.callback_timeout)
+      {:error, reason} -> {:error, reason}
+    end
+  end
+
+  # Nested callbacks (imported functions called by the nested call) are invoked while
+  # waiting, with the state of the callback the nested call was made from.
+  defp await_nested_call(ref, callback_state, timeout) do
+    receive do
+      {:returned_function_call, result, ^ref} ->
+        result
+
+      {:invoke_callback, namespace_name, import_name, context, params, token} ->
+        invoke_callback(callback_state, namespace_name, import_name, context, params, token)
+        await_nested_call(ref, callback_state, timeout)
+    after
+      timeout || :infinity -> {:error, :timeout}
+    end
+  end
+
+  @doc """
+  Cancels the function call currently running in the WebAssembly instance.
+
+  The cancelled call returns `{:error, :cancelled}`. If no call is running, nothing is
+  cancelled.
+  Requires the instance's engine to be configured with `epoch_interruption: true`,
+  see `WasmexWasmtime.EngineConfig`.
+  """
+  def cancel_call(pid) do
+    GenServer.call(pid, {:cancel_call})
   end
 
   @doc """
   Finds the exported memory of the given WASM instance and returns it as a `WasmexWasmtime.Memory`.
 
   The memory is a sequence of bytes.
+  Without a `name`, the memory exported as `"memory"` is returned, or else the first exported
+  memory. Otherwise the memory exported under the given `name`, for modules exporting several
+  memories.
   """
-  def memory(pid) do
-    GenServer.call(pid, {:memory})
+  def memory(pid, name \\ nil) do
+    GenServer.call(pid, {:memory, name})
   end
 
   defp stringify_keys(struct) when is_struct(struct), do: struct
@@ -272,20 +453,30 @@ defmodule WasmexWasmtime do
   """
   @impl true
   def init(%{store: store, module: module, imports: imports} = state) when is_map(imports) do
-    case WasmexWasmtime.Instance.new(store, module, imports) do
-      {:ok, instance} -> {:ok, Map.merge(state, %{instance: instance})}
-      {:error, reason} -> {:error, reason}
+    instance_opts = [callback_timeout: state.callback_timeout, callback_pid: state.callback_pid]
+
+    case WasmexWasmtime.Instance.new(store, module, imports, instance_opts) do
+      {:ok, instance} ->
+        {:ok, Map.merge(state, %{instance: instance})}
+
+      {:error, reason} ->
+        {:error, reason}
     end
   end
 
   @impl true
-  def handle_call({:memory}, _from, %{store: store, instance: instance} = state) do
-    case WasmexWasmtime.Memory.from_instance(store, instance) do
+  def handle_call({:memory, name}, _from, %{store: store, instance: instance} = state) do
+    case WasmexWasmtime.Memory.from_instance(store, instance, name) do
       {:ok, memory} -> {:reply, {:ok, memory}, state}
       {:error, error} -> {:reply, {:error, error}, state}
     end
   end
 
+  @impl true
+  def handle_call({:cancel_call}, _from, %{store: store} = state) do
+    {:reply, WasmexWasmtime.StoreOrCaller.cancel_call(store), state}
+  end
+
   @impl true
   def handle_call(
         {:exported_function_exists, name},
@@ -298,11 +489,29 @@ defmodule WasmexWasmtime do
 
   @impl true
   def handle_call(
-        {:call_function, name, params},
+        {:call_function, name, params, timeout},
         from,
         %{store: store, instance: instance} = state
       ) do
-    :ok = WasmexWasmtime.Instance.call_exported_function(store, instance, name, params, from)
+    opts = call_opts(store, timeout)
+
+    :ok =
+      WasmexWasmtime.Instance.call_exported_function(store, instance, name, params, from, opts)
+
+    {:noreply, state}
+  end
+
+  # the caller stops waiting after `timeout`, so the call is interrupted once it is reached
+  defp call_opts(store, timeout) when is_integer(timeout) do
+    if WasmexWasmtime.StoreOrCaller.epoch_interruption?(store), do: [timeout: timeout], else: []
+  end
+
+  defp call_opts(_store, :infinity), do: []
+  end
+
+  # late results of nested calls `call_function_from_callback/3` stopped waiting for
+  @impl true
+  def handle_info({:returned_function_call, _result, ref}, state) when is_reference(ref) do
     {:noreply, state}
   end
 
@@ -315,36 +524,110 @@ defmodule WasmexWasmtime do
   @impl true
   def handle_info(
         {:invoke_callback, namespace_name, import_name, context, params, token},
-        %{imports: imports} = state
+        state
+      ) do
+    callback_state = Map.take(state, [:imports, :instance])
+    invoke_callback(callback_state, namespace_name, import_name, context, params, token)
+    {:noreply, state}
+  end
+
+  @impl true
+  def terminate(_reason, %{instance: instance}) do
+    WasmexWasmtime.Instance.stop_callback_forwarders(instance)
+  end
+
+  @doc """
+  Runs the imported function callback of an `:invoke_callback` message and returns its
+  result to the waiting WebAssembly call.
+
+  Callbacks are sent to the process given as `callback_pid` (see `start_link/1`), which
+  passes the messages on together with the imports of the instance. Give the `instance`
+  to allow `call_function_from_callback/3` within callbacks. E.g. a callback worker:
+
+      def serve_callbacks(imports, instance) do
+        receive do
+          {:invoke_callback, _namespace, _name, _context, _params, _token} = message ->
+            :ok = WasmexWasmtime.handle_callback(message, imports, instance)
+            serve_callbacks(imports, instance)
+        end
+      end
+  """
+  @spec handle_callback(tuple(), map(), WasmexWasmtime.Instance.t() | nil) :: :ok
+  def handle_callback(
+        {:invoke_callback, namespace_name, import_name, context, params, token},
+        imports,
+        instance \\ nil
       ) do
+    callback_state = %{imports: stringify_keys(imports), instance: instance}
+    invoke_callback(callback_state, namespace_name, import_name, context, params, token)
+  end
+
+  defp invoke_callback(
+         %{imports: imports, instance: instance} = callback_state,
+         namespace_name,
+         import_name,
+         context,
+         params,
+         token
+       ) do
     context =
       Map.merge(
         context,
         %{
-          memory: WasmexWasmtime.Memory.wrap_resource(Map.get(context, :memory)),
-          caller: WasmexWasmtime.StoreOrCaller.wrap_resource(Map.get(context, :caller))
+          memory: wrap_memory(Map.get(context, :memory)),
+          memories: wrap_exports(context, :memories, &wrap_memory/1),
+          globals: wrap_exports(context, :globals, &WasmexWasmtime.Global.wrap_resource/1),
+          tables: wrap_exports(context, :tables, &WasmexWasmtime.Table.wrap_resource/1),
+          functions: wrap_exports(context, :functions, &WasmexWasmtime.Function.wrap_resource/1),
+          caller: WasmexWasmtime.StoreOrCaller.wrap_resource(Map.get(context, :caller)),
+          instance: instance
         }
       )
 
-    {success, return_value} =
+    # nested callbacks of `call_function_from_callback/3` are run with the same state
+    previous_callback_state = Process.put(@callback_state, callback_state)
+
+    {success, result} =
       try do
-        {:fn, _params, _returns, callback} =
+        callback =
           imports
           |> Map.get(namespace_name, %{})
           |> Map.get(import_name)
-
-        {true, apply(callback, [context | params])}
-      rescue
-        e in RuntimeError -> {false, e.message}
+          |> elem(3)
+
+        case apply(callback, [context | params]) do
+          nil -> {true, []}
+          {:trap, reason} when is_binary(reason) -> {false, {:trap, reason}}
+          {:exit, code} when is_integer(code) -> {false, {:exit, code}}
+          return_value -> {true, [return_value]}
+        end
+      catch
+        kind, reason -> {false, callback_error(kind, reason, __STACKTRACE__)}
+      after
+        restore_callback_state(previous_callback_state)
       end
 
-    return_values =
-      case return_value do
-        nil -> []
-        _ -> [return_value]
-      end
+    :ok = WasmexWasmtime.Native.instance_receive_callback_result(token, success, result)
+  end
 
-    :ok = WasmexWasmtime.Native.instance_receive_callback_result(token, success, return_values)
-    {:noreply, state}
+  defp restore_callback_state(nil), do: Process.delete(@callback_state)
+  defp restore_callback_state(callback_state), do: Process.put(@callback_state, callback_state)
+  end
+
+  defp callback_error(kind, reason, stacktrace) do
+    %{
+      kind: kind,
+      message: Exception.format_banner(kind, reason, stacktrace),
+      stacktrace: Exception.format_stacktrace(stacktrace)
+    }
   end
+
+  defp wrap_exports(context, key, wrap) do
+    context
+    |> Map.get(key)
+    |> Map.new(fn {name, resource} -> {name, wrap.(resource)} end)
+  end
+
+  defp wrap_memory(nil), do: nil
+  defp wrap_memory(resource), do: WasmexWasmtime.Memory.wrap_resource(resource)
 end
@@ -0,0 +1,97 @@
+defmodule WasmexWasmtime.Engine do
+  @moduledoc ~S"""
+  An engine is the global context for compiling and running WebAssembly modules.
+
+  Stores and modules are created from an engine. A module can be instantiated in
+  every store created from the same engine, so an engine is usually created once
+  and shared.
+
+      {:ok, engine} = WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{})
+      {:ok, module} = WasmexWasmtime.Module.compile(engine, bytes)
+      {:ok, store_a} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, store_b} = WasmexWasmtime.Store.new(engine: engine)
+
+      {:ok, instance_a} = WasmexWasmtime.start_link(%{store: store_a, module: module})
+      {:ok, instance_b} = WasmexWasmtime.start_link(%{store: store_b, module: module})
+  """
+
+  alias WasmexWasmtime.EngineConfig
+
+  @type t :: %__MODULE__{
+          resource: binary(),
+          reference: reference()
+        }
+
+  defstruct resource: nil,
+            # The actual NIF engine resource.
+            # Normally the compiler will happily do stuff like inlining the
+            # resource in attributes. This will convert the resource into an
+            # empty binary with no warning. This will make that harder to
+            # accidentally do.
+            reference: nil
+
+  @doc """
+  Creates a new engine with the given `WasmexWasmtime.EngineConfig`.
+  """
+  @spec new(EngineConfig.t()) :: {:ok, __MODULE__.t()} | {:error, binary()}
+  def new(%EngineConfig{} = config \\ %EngineConfig{}) do
+    case WasmexWasmtime.Native.engine_new(config) do
+      {:ok, resource} -> {:ok, wrap_resource(resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Creates a new engine with the default `WasmexWasmtime.EngineConfig`.
+
+  Every call creates a separate engine. Modules can only be used with stores of the
+  engine they were compiled with.
+  """
+  @spec new_default() :: __MODULE__.t()
+  def new_default() do
+    {:ok, engine} = new(%EngineConfig{})
+    engine
+  end
+
+  @doc """
+  Precompiles WASM (or WAT) `bytes` into a native artifact without creating a store or module.
+
+  The artifact can be loaded with `WasmexWasmtime.Module.unsafe_deserialize/2` into an engine
+  with a compatible config. Set `target` in the `WasmexWasmtime.EngineConfig` to cross-compile
+  for another platform:
+
+      {:ok, engine} = WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{target: "aarch64-unknown-linux-gnu"})
+      {:ok, serialized} = WasmexWasmtime.Engine.precompile_module(engine, bytes)
+
+  Instead of an engine, an `WasmexWasmtime.EngineConfig` may be given.
+  """
+  @spec precompile_module(__MODULE__.t() | EngineConfig.t(), binary()) ::
+          {:ok, binary()} | {:error, binary()}
+  def precompile_module(%EngineConfig{} = config, bytes) when is_binary(bytes) do
+    with {:ok, engine} <- new(config) do
+      precompile_module(engine, bytes)
+    end
+  end
+
+  def precompile_module(%__MODULE__{resource: resource}, bytes) when is_binary(bytes) do
+    case WasmexWasmtime.Native.engine_precompile_module(resource, bytes) do
+      {:error, err} -> {:error, err}
+      serialized -> {:ok, serialized}
+    end
+  end
+
+  def wrap_resource(resource) do
+    %__MODULE__{
+      resource: resource,
+      reference: make_ref()
+    }
+  end
+end
+
+defimpl Inspect, for: WasmexWasmtime.Engine do
+  import Inspect.Algebra
+
+  def inspect(dict, opts) do
+    concat(["#WasmexWasmtime.Engine<", to_doc(dict.reference, opts), ">"])
+  end
+end
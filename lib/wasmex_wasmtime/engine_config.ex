@@ -0,0 +1,72 @@
+defmodule WasmexWasmtime.EngineConfig do
+  @moduledoc ~S"""
+  Configures the wasmtime engine used to compile and run WebAssembly modules.
+
+  All options default to the wasmtime defaults.
+  Pass a config to `WasmexWasmtime.Engine.new/1`:
+
+      config = %WasmexWasmtime.EngineConfig{wasm_multi_memory: true, cranelift_opt_level: :speed_and_size}
+      {:ok, engine} = WasmexWasmtime.Engine.new(config)
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+
+  ## Options
+
+    * `:consume_fuel` - whether executing WebAssembly consumes fuel. When enabled, a store
+      starts without fuel and traps once it runs out, see `WasmexWasmtime.StoreOrCaller.add_fuel/2`.
+      Defaults to `false`.
+    * `:cranelift_opt_level` - the Cranelift optimization level. One of `:none`, `:speed`
+      (default), or `:speed_and_size`. Lower levels compile faster but run slower.
+    * `:epoch_interruption` - whether running WebAssembly can be interrupted. Enables call
+      timeouts and cancellation, see `WasmexWasmtime.Instance.call_exported_function/6` and
+      `WasmexWasmtime.StoreOrCaller.cancel_call/2`. Defaults to `false`.
+    * `:epoch_tick_interval` - the interval in milliseconds at which running calls check
+      whether they must be interrupted. Only used with `:epoch_interruption`. Defaults to `10`.
+    * `:parallel_compilation` - whether functions of a module are compiled in parallel using
+      multiple threads. Defaults to `true`.
+    * `:target` - the target triple to compile for, e.g. `"aarch64-unknown-linux-gnu"`.
+      Engines with a target other than the host can only precompile modules, see
+      `WasmexWasmtime.Engine.precompile_module/2`. Defaults to `nil`, the host.
+    * `:wasm_backtrace_details` - whether trap backtraces include DWARF filename and line
+      number info. Defaults to `false`.
+    * `:wasm_bulk_memory` - enables the bulk memory proposal. Defaults to `true`.
+    * `:wasm_memory64` - enables the memory64 proposal. Defaults to `false`.
+    * `:wasm_multi_memory` - enables the multi-memory proposal. Defaults to `false`.
+    * `:wasm_multi_value` - enables the multi-value proposal. Defaults to `true`.
+    * `:wasm_reference_types` - enables the reference types proposal. Defaults to `true`.
+      Requires `:wasm_bulk_memory`.
+    * `:wasm_simd` - enables the fixed-width SIMD proposal. Defaults to `true`.
+    * `:wasm_threads` - enables the threads proposal. Defaults to `false`.
+  """
+
+  defstruct consume_fuel: false,
+            cranelift_opt_level: :speed,
+            epoch_interruption: false,
+            epoch_tick_interval: 10,
+            parallel_compilation: true,
+            target: nil,
+            wasm_backtrace_details: false,
+            wasm_bulk_memory: true,
+            wasm_memory64: false,
+            wasm_multi_memory: false,
+            wasm_multi_value: true,
+            wasm_reference_types: true,
+            wasm_simd: true,
+            wasm_threads: false
+
+  @type t :: %__MODULE__{
+          consume_fuel: boolean(),
+          cranelift_opt_level: :none | :speed | :speed_and_size,
+          epoch_interruption: boolean(),
+          epoch_tick_interval: pos_integer(),
+          parallel_compilation: boolean(),
+          target: binary() | nil,
+          wasm_backtrace_details: boolean(),
+          wasm_bulk_memory: boolean(),
+          wasm_memory64: boolean(),
+          wasm_multi_memory: boolean(),
+          wasm_multi_value: boolean(),
+          wasm_reference_types: boolean(),
+          wasm_simd: boolean(),
+          wasm_threads: boolean()
+        }
+end
@@ -0,0 +1,63 @@
+defmodule WasmexWasmtime.Function do
+  @moduledoc """
+  A handle to a WebAssembly function, e.g. read from a `WasmexWasmtime.Table`.
+
+      {:ok, table} = WasmexWasmtime.Table.from_instance(store, instance, "__indirect_function_table")
+      {:ok, function} = WasmexWasmtime.Table.get(store, table, 1)
+      :ok = WasmexWasmtime.Function.call(store, function, [1, 2], from)
+
+  A function handle can only be used with the store it belongs to, calls with other
+  stores return an error.
+  """
+
+  @type t :: %__MODULE__{
+          resource: binary(),
+          reference: reference()
+        }
+
+  defstruct resource: nil,
+            # The actual NIF function resource.
+            # Normally the compiler will happily do stuff like inlining the
+            # resource in attributes. This will convert the resource into an
+            # empty binary with no warning. This will make that harder to
+            # accidentally do.
+            reference: nil
+
+  @doc """
+  Calls the function with the given `params`.
+
+  Works like `WasmexWasmtime.Instance.call_exported_function/6`: the function is invoked
+  asynchronously in a new OS thread and the calling process receives a
+  `{:returned_function_call, result, from}` message once the execution finished.
+  Accepts the same options.
+  """
+  @spec call(WasmexWasmtime.StoreOrCaller.t(), t(), [any()], GenServer.from(), keyword()) ::
+          :ok | {:error, binary()}
+  def call(store_or_caller, function, params, from, opts \\ []) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %__MODULE__{resource: resource} = function
+
+    WasmexWasmtime.Native.function_call(
+      store_or_caller_resource,
+      resource,
+      params,
+      Keyword.get(opts, :timeout),
+      from
+    )
+  end
+
+  def wrap_resource(resource) do
+    %__MODULE__{
+      resource: resource,
+      reference: make_ref()
+    }
+  end
+end
+
+defimpl Inspect, for: WasmexWasmtime.Function do
+  import Inspect.Algebra
+
+  def inspect(dict, opts) do
+    concat(["#WasmexWasmtime.Function<", to_doc(dict.reference, opts), ">"])
+  end
+end
@@ -0,0 +1,92 @@
+defmodule WasmexWasmtime.Global do
+  @moduledoc """
+  A global exported by a WebAssembly instance.
+
+      {:ok, global} = WasmexWasmtime.Global.from_instance(store, instance, "stack_pointer")
+      WasmexWasmtime.Global.get(store, global) # 1_048_576
+      :ok = WasmexWasmtime.Global.set(store, global, 2_097_152)
+      WasmexWasmtime.Global.type(store, global) # {:global, %{mutability: :var, type: :i32}}
+  """
+
+  @type t :: %__MODULE__{
+          resource: binary(),
+          reference: reference()
+        }
+
+  defstruct resource: nil,
+            # The actual NIF global resource.
+            # Normally the compiler will happily do stuff like inlining the
+            # resource in attributes. This will convert the resource into an
+            # empty binary with no warning. This will make that harder to
+            # accidentally do.
+            reference: nil
+
+  @doc """
+  Returns the global exported by the given `instance` under the given `name`.
+  """
+  @spec from_instance(WasmexWasmtime.StoreOrCaller.t(), WasmexWasmtime.Instance.t(), binary()) ::
+          {:ok, t()} | {:error, binary()}
+  def from_instance(store_or_caller, instance, name) when is_binary(name) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %WasmexWasmtime.Instance{resource: instance_resource} = instance
+
+    case WasmexWasmtime.Native.global_from_instance(
+           store_or_caller_resource,
+           instance_resource,
+           name
+         ) do
+      {:ok, resource} -> {:ok, wrap_resource(resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Returns the current value of the global.
+  """
+  @spec get(WasmexWasmtime.StoreOrCaller.t(), t()) :: number() | {:error, binary()}
+  def get(store_or_caller, global) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %__MODULE__{resource: resource} = global
+    WasmexWasmtime.Native.global_get(store_or_caller_resource, resource)
+  end
+
+  @doc """
+  Sets the value of the global.
+
+  Returns an error if the global is immutable (`:const`) or the value does not match
+  the type of the global.
+  """
+  @spec set(WasmexWasmtime.StoreOrCaller.t(), t(), number()) :: :ok | {:error, binary()}
+  def set(store_or_caller, global, value) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %__MODULE__{resource: resource} = global
+    WasmexWasmtime.Native.global_set(store_or_caller_resource, resource, value)
+  end
+
+  @doc """
+  Returns the type of the global in the shape `WasmexWasmtime.Module.exports/1` reports globals:
+
+      {:global, %{mutability: :var, type: :i32}}
+  """
+  @spec type(WasmexWasmtime.StoreOrCaller.t(), t()) :: {:global, map()} | {:error, binary()}
+  def type(store_or_caller, global) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %__MODULE__{resource: resource} = global
+    WasmexWasmtime.Native.global_type(store_or_caller_resource, resource)
+  end
+
+  def wrap_resource(resource) do
+    %__MODULE__{
+      resource: resource,
+      reference: make_ref()
+    }
+  end
+end
+
+defimpl Inspect, for: WasmexWasmtime.Global do
+  import Inspect.Algebra
+
+  def inspect(dict, opts) do
+    concat(["#WasmexWasmtime.Global<", to_doc(dict.reference, opts), ">"])
+  end
+end
@@ -30,7 +30,8 @@ defmodule WasmexWasmtime.Instance do
 
   @type t :: %__MODULE__{
           resource: binary(),
-          reference: reference()
+          reference: reference(),
+          callback_forwarders: [pid()]
         }
 
   defstruct resource: nil,
@@ -39,26 +40,172 @@ defmodule WasmexWasmtime.Instance do
             # resource in attributes. This will convert the resource into an
             # empty binary with no warning. This will make that harder to
             # accidentally do.
-            reference: nil
+            reference: nil,
+            # processes forwarding callbacks to registered names, see `new/4`
+            callback_forwarders: []
 
-  @spec new(WasmexWasmtime.StoreOrCaller.t(), WasmexWasmtime.Module.t(), %{
-          optional(binary()) => (... -> any())
-        }) ::
+  @doc """
+  Instantiates the given module, linking the given imports.
+
+  Imported functions send their calls to the calling process and block the WebAssembly
+  execution until it returns a result. Options:
+
+  - `:callback_timeout` - the time in milliseconds an imported function waits for its
+    result before trapping, the call then returns `{:error, :callback_timeout}`.
+    Waits forever by default.
+  - `:callback_pid` - the pid or registered name of the process receiving the callbacks
+    of imported functions, defaults to the calling process. A single imported function
+    names its own process as fifth element of its tuple, e.g.
+    `{:fn, [:i32], [:i32], callback, :callback_worker}`.
+
+  Registered names are looked up for every callback, so that callbacks reach a restarted
+  process registered under the same name. Each name must be registered when instantiating,
+  `{:error, reason}` is returned otherwise. Callbacks sent while no process is registered
+  under the name return `{:error, :callback_abandoned}`. See `WasmexWasmtime.handle_callback/3`
+  for serving callbacks in other processes.
+
+  Callbacks to registered names are passed on by forwarder processes linked to the calling
+  process. They run until the calling process exits or `stop_callback_forwarders/1` is called
+  for the instance, which `WasmexWasmtime` does when its GenServer terminates.
+
+  If the process receiving the callback exits without returning a result, the call
+  returns `{:error, :callback_abandoned}`. Callbacks failing with an exception return
+  `{:error, {:callback_error, %{kind: kind, message: message, stacktrace: stacktrace}}}`.
+  Callbacks returning `{:trap, reason}` or `{:exit, code}` abort the call, which then returns
+  `{:error, {:trap, reason}}` or `{:error, {:exit, code}}`.
+  """
+  @spec new(
+          WasmexWasmtime.StoreOrCaller.t(),
+          WasmexWasmtime.Module.t(),
+          %{optional(binary()) => (... -> any())},
+          keyword()
+        ) ::
           {:ok, __MODULE__.t()} | {:error, binary()}
-  def new(store_or_caller, module, imports) when is_map(imports) do
+  def new(store_or_caller, module, imports, opts \\ []) when is_map(imports) do
     %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
     %WasmexWasmtime.Module{resource: module_resource} = module
 
-    case WasmexWasmtime.Native.instance_new(store_or_caller_resource, module_resource, imports) do
-      {:ok, resource} -> {:ok, wrap_resource(resource)}
-      {:error, err} -> {:error, err}
+    callback_timeout = Keyword.get(opts, :callback_timeout)
+    callback_pid = Keyword.get(opts, :callback_pid)
+
+    with {:ok, forwarders} <- start_callback_forwarders(imports, callback_pid) do
+      case WasmexWasmtime.Native.instance_new(
+             store_or_caller_resource,
+             module_resource,
+             prepare_imports(imports, forwarders),
+             callback_timeout,
+             callback_target(callback_pid, forwarders)
+           ) do
+        {:ok, resource} ->
+          {:ok, wrap_resource(resource, Map.values(forwarders))}
+
+        {:error, err} ->
+          Enum.each(Map.values(forwarders), &stop_callback_forwarder/1)
+          {:error, err}
+      end
+    end
+  end
+
+  @doc """
+  Stops the processes forwarding callbacks of the instance to registered names.
+
+  Callbacks to registered names return `{:error, :callback_abandoned}` afterwards.
+  """
+  @spec stop_callback_forwarders(t()) :: :ok
+  def stop_callback_forwarders(%__MODULE__{callback_forwarders: forwarders}) do
+    Enum.each(forwarders, &stop_callback_forwarder/1)
+  end
+
+  defp stop_callback_forwarder(forwarder) do
+    Process.unlink(forwarder)
+    Process.exit(forwarder, :kill)
+  end
+
+  defp prepare_imports(imports, forwarders) do
+    Map.new(imports, fn {namespace_name, namespace} ->
+      {namespace_name,
+       Map.new(namespace, fn {name, import} -> {name, prepare_import(import, forwarders)} end)}
+    end)
+  end
+
+  defp prepare_import({:memory, %WasmexWasmtime.Memory{resource: resource}}, _forwarders),
+    do: {:memory, resource}
+
+  defp prepare_import({:memory, %{} = memory_type}, _forwarders),
+    do: {:memory, Map.merge(%{maximum: nil, shared: false}, memory_type)}
+
+  defp prepare_import({:table, %WasmexWasmtime.Table{resource: resource}}, _forwarders),
+    do: {:table, resource}
+
+  defp prepare_import({:fn, params, results, callback, nil}, _forwarders),
+    do: {:fn, params, results, callback}
+
+  defp prepare_import({:fn, params, results, callback, callback_pid}, forwarders),
+    do: {:fn, params, results, callback, callback_target(callback_pid, forwarders)}
+
+  defp prepare_import(import, _forwarders), do: import
+
+  defp callback_target(name, forwarders) when is_atom(name) and not is_nil(name),
+    do: Map.fetch!(forwarders, name)
+
+  defp callback_target(pid_or_nil, _forwarders), do: pid_or_nil
+
+  # The native side sends callbacks to pids only. Callbacks for registered names are sent
+  # to a forwarder, which looks up the name for every callback.
+  defp start_callback_forwarders(imports, callback_pid) do
+    names =
+      for {_namespace_name, namespace} <- imports,
+          {_name, {:fn, _params, _results, _callback, name}} <- namespace,
+          do: name
+
+    names =
+      [callback_pid | names]
+      |> Enum.filter(&(is_atom(&1) and not is_nil(&1)))
+      |> Enum.uniq()
+
+    case Enum.find(names, &is_nil(Process.whereis(&1))) do
+      nil ->
+        {:ok, Map.new(names, &{&1, start_callback_forwarder(&1)})}
+
+      name ->
+        {:error, "no process registered as #{inspect(name)} to receive callbacks"}
     end
   end
 
-  defp wrap_resource(resource) do
+  # Forwards callbacks as long as the process creating the instance is alive. The link stops
+  # the forwarder if the process crashes, the monitor also if it exits normally.
+  # Callbacks are dropped while no process is registered under the name, the waiting
+  # WebAssembly call then sees the callback as abandoned.
+  defp start_callback_forwarder(name) do
+    owner = self()
+
+    spawn_link(fn ->
+      owner_ref = Process.monitor(owner)
+      forward_callbacks(name, owner_ref)
+    end)
+  end
+
+  defp forward_callbacks(name, owner_ref) do
+    receive do
+      {:DOWN, ^owner_ref, :process, _pid, _reason} ->
+        :ok
+
+      message ->
+        case Process.whereis(name) do
+          # frees the dropped callback token right away
+          nil -> :erlang.garbage_collect()
+          pid -> send(pid, message)
+        end
+
+        forward_callbacks(name, owner_ref)
+    end
+  end
+
+  defp wrap_resource(resource, callback_forwarders) do
     %__MODULE__{
       resource: resource,
-      reference: make_ref()
+      reference: make_ref(),
+      callback_forwarders: callback_forwarders
     }
   end
 
@@ -84,6 +231,25 @@ defmodule WasmexWasmtime.Instance do
   The calling process will receive a `{:returned_function_call, result, from}` message once
   the execution finished.
   The result either is an `{:error, reason}` or the `:ok` atom.
+  Calls running out of fuel (see `WasmexWasmtime.StoreOrCaller.add_fuel/2`) result in
+  `{:error, :out_of_fuel}`.
+
+  ## Options
+
+    * `:timeout` - the maximum time in milliseconds the WebAssembly function may run.
+      Once exceeded, the call is interrupted and results in `{:error, :timeout}`.
+      Requires `epoch_interruption: true` in the `WasmexWasmtime.EngineConfig` of the
+      store's engine. The deadline is checked on every epoch tick, so calls may run up to
+      `epoch_tick_interval` longer than the timeout. Calls through a caller run under the
+      deadline of the outer call and can not be given a timeout.
+
+  A running call can be cancelled with `WasmexWasmtime.StoreOrCaller.cancel_call/2`,
+  which results in `{:error, :cancelled}`.
+
+  Given the `caller` from the context of an imported function callback, the function is called
+  re-entrantly: it runs nested in the paused WebAssembly call which invoked the callback, once
+  the calling process is waiting for the result. Calling through a caller whose callback already
+  returned results in `{:error, reason}`. See `WasmexWasmtime.call_function_from_callback/3`.
 
   A BadArg exception may be thrown when given unexpected input data.
   """
@@ -92,10 +258,11 @@ defmodule WasmexWasmtime.Instance do
           __MODULE__.t(),
           binary(),
           [any()],
-          GenServer.from()
+          GenServer.from(),
+          keyword()
         ) ::
           :ok | {:error, binary()}
-  def call_exported_function(store_or_caller, instance, name, params, from)
+  def call_exported_function(store_or_caller, instance, name, params, from, opts \\ [])
       when is_binary(name) do
     %{resource: store_or_caller_resource} = store_or_caller
     %__MODULE__{resource: instance_resource} = instance
@@ -105,6 +272,7 @@ defmodule WasmexWasmtime.Instance do
       instance_resource,
       name,
       params,
+      Keyword.get(opts, :timeout),
       from
     )
   end
@@ -97,18 +97,80 @@ defmodule WasmexWasmtime.Memory do
             # accidentally do.
             reference: nil
 
-  @spec from_instance(WasmexWasmtime.StoreOrCaller.t(), WasmexWasmtime.Instance.t()) ::
+  @doc """
+  Creates a new memory in the given store.
+
+  The memory type is given as a map of `:minimum` and (optionally) `:maximum` size in pages.
+  The memory can be imported into instances of the same store, e.g. to prefill data
+  before instantiation:
+
+      {:ok, memory} = WasmexWasmtime.Memory.new(store, %{minimum: 1, maximum: 2})
+      WasmexWasmtime.Memory.write_binary(store, memory, 0, "hello")
+      imports = %{env: %{memory: {:memory, memory}}}
+  """
+  @spec new(WasmexWasmtime.StoreOrCaller.t(), %{
+          required(:minimum) => non_neg_integer(),
+          optional(:maximum) => non_neg_integer() | nil
+        }) ::
           {:ok, t} | {:error, binary()}
-  def from_instance(store_or_caller, instance) do
+  def new(store_or_caller, %{minimum: _} = memory_type) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    memory_type = Map.merge(%{maximum: nil, shared: false}, memory_type)
+
+    case WasmexWasmtime.Native.memory_new(store_or_caller_resource, memory_type) do
+      {:ok, memory_resource} -> {:ok, wrap_resource(memory_resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Returns a memory exported by the given `instance`.
+
+  Without a `name`, the memory exported as `"memory"` is returned, or else the first exported
+  memory. This is the same memory imported function callbacks receive as `:memory`. Modules with several memories
+  (see the `wasm_multi_memory` option of `WasmexWasmtime.EngineConfig`) can address each
+  memory by its export name, see `export_names/2`.
+  """
+  @spec from_instance(
+          WasmexWasmtime.StoreOrCaller.t(),
+          WasmexWasmtime.Instance.t(),
+          binary() | nil
+        ) ::
+          {:ok, t} | {:error, binary()}
+  def from_instance(store_or_caller, instance, name \\ nil) do
     %{resource: store_or_caller_resource} = store_or_caller
     %WasmexWasmtime.Instance{resource: instance_resource} = instance
 
-    case WasmexWasmtime.Native.memory_from_instance(store_or_caller_resource, instance_resource) do
+    result =
+      case name do
+        nil ->
+          WasmexWasmtime.Native.memory_from_instance(store_or_caller_resource, instance_resource)
+
+        name when is_binary(name) ->
+          WasmexWasmtime.Native.memory_from_instance_by_name(
+            store_or_caller_resource,
+            instance_resource,
+            name
+          )
+      end
+
+    case result do
       {:ok, memory_resource} -> {:ok, wrap_resource(memory_resource)}
       {:error, err} -> {:error, err}
     end
   end
 
+  @doc """
+  Returns the export names of all memories exported by the given `instance`.
+  """
+  @spec export_names(WasmexWasmtime.StoreOrCaller.t(), WasmexWasmtime.Instance.t()) ::
+          [binary()] | {:error, binary()}
+  def export_names(store_or_caller, instance) do
+    %{resource: store_or_caller_resource} = store_or_caller
+    %WasmexWasmtime.Instance{resource: instance_resource} = instance
+    WasmexWasmtime.Native.memory_export_names(store_or_caller_resource, instance_resource)
+  end
+
   def wrap_resource(resource) do
     %__MODULE__{
       resource: resource,
@@ -33,9 +33,23 @@ defmodule WasmexWasmtime.Module do
   Since module compilation takes time and resources but instantiation is comparatively cheap, it
   may be a good idea to compile a module once and instantiate it often if you want to
   run a WASM binary multiple times.
+
+  The module is compiled with the given `WasmexWasmtime.Engine`, or the engine of the given store.
+  It can be instantiated in every store created from that engine.
+
+  Compilation runs on a dirty CPU scheduler, so compiling large modules does not block
+  regular schedulers. See `parallel_compilation` in `WasmexWasmtime.EngineConfig`.
   """
-  @spec compile(WasmexWasmtime.StoreOrCaller.t(), binary()) ::
+  @spec compile(WasmexWasmtime.Engine.t() | WasmexWasmtime.StoreOrCaller.t(), binary()) ::
           {:ok, __MODULE__.t()} | {:error, binary()}
+  def compile(%WasmexWasmtime.Engine{resource: engine_resource}, bytes)
+      when is_binary(bytes) do
+    case WasmexWasmtime.Native.module_compile_with_engine(engine_resource, bytes) do
+      {:ok, resource} -> {:ok, wrap_resource(resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
   def compile(%WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource}, bytes)
       when is_binary(bytes) do
     case WasmexWasmtime.Native.module_compile(store_or_caller_resource, bytes) do
@@ -44,6 +58,62 @@ defmodule WasmexWasmtime.Module do
     end
   end
 
+  @doc """
+  Compiles a WASM module from a `.wasm` or `.wat` file at the given `path`.
+
+  Works like `compile/2`, but the file is read by the NIF directly, so its bytes never
+  enter the BEAM heap. Prefer this for large modules.
+  """
+  @spec compile_file(WasmexWasmtime.Engine.t() | WasmexWasmtime.StoreOrCaller.t(), Path.t()) ::
+          {:ok, __MODULE__.t()} | {:error, binary()}
+  def compile_file(%WasmexWasmtime.StoreOrCaller{} = store, path) do
+    with {:ok, engine} <- WasmexWasmtime.StoreOrCaller.engine(store) do
+      compile_file(engine, path)
+    end
+  end
+
+  def compile_file(%WasmexWasmtime.Engine{resource: engine_resource}, path) do
+    case WasmexWasmtime.Native.module_compile_file(engine_resource, Path.expand(path)) do
+      {:ok, resource} -> {:ok, wrap_resource(resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Validates WASM (or WAT) `bytes` without compiling them.
+
+  Validation uses the wasm proposals enabled for the given `WasmexWasmtime.Engine`
+  (or the engine of the given store). Returns `:ok` for valid modules, otherwise
+  `{:error, error}` with `error` being a map of:
+
+  * `:message` - a description of the problem
+  * `:offset` - the byte offset of the problem in `bytes`, if known
+  * `:line` and `:column` - the 1-based position of the problem in WAT input, `nil` for WASM input
+  * `:feature` - the disabled wasm proposal the module uses (e.g. `:simd`, `:threads`), or `nil`
+
+      {:ok, engine} = WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{wasm_simd: false})
+      {:error, %{feature: :simd}} = WasmexWasmtime.Module.validate(engine, bytes)
+  """
+  @spec validate(WasmexWasmtime.Engine.t() | WasmexWasmtime.StoreOrCaller.t(), binary()) ::
+          :ok
+          | {:error,
+             %{
+               message: binary(),
+               offset: non_neg_integer() | nil,
+               line: pos_integer() | nil,
+               column: pos_integer() | nil,
+               feature: atom() | nil
+             }}
+  def validate(%WasmexWasmtime.StoreOrCaller{} = store, bytes) when is_binary(bytes) do
+    with {:ok, engine} <- WasmexWasmtime.StoreOrCaller.engine(store) do
+      validate(engine, bytes)
+    end
+  end
+
+  def validate(%WasmexWasmtime.Engine{resource: engine_resource}, bytes) when is_binary(bytes) do
+    WasmexWasmtime.Native.module_validate(engine_resource, bytes)
+  end
+
   @doc """
   Returns the name of the current module if a name is given.
 
@@ -98,8 +168,8 @@ defmodule WasmexWasmtime.Module do
   @doc """
   Serializes a compiled WASM module into a binary.
 
-  The generated binary can be deserialized back into a module using `unsafe_deserialize/1`.
-  It is unsafe do alter the binary in any way. See `unsafe_deserialize/1` for safety considerations.
+  The generated binary can be deserialized back into a module using `unsafe_deserialize/2`.
+  It is unsafe do alter the binary in any way. See `unsafe_deserialize/2` for safety considerations.
   """
   @spec serialize(__MODULE__.t()) :: {:ok, binary()} | {:error, binary()}
   def serialize(%__MODULE__{resource: resource}) do
@@ -122,16 +192,94 @@ defmodule WasmexWasmtime.Module do
 
   The deserialization must be done on the same CPU architecture as the serialization
   (e.g. don't serialize a x86_64-compiled module and deserialize it on ARM64).
+
+  The module is deserialized into the given `WasmexWasmtime.Engine`, or the engine of the
+  given store, and can be instantiated in every store created from that engine.
+  Use `check_compatibility/2` to find out whether a binary can be deserialized into an engine.
   """
+  @spec unsafe_deserialize(
+          binary(),
+          WasmexWasmtime.Engine.t() | WasmexWasmtime.StoreOrCaller.t()
+        ) ::
+          {:ok, __MODULE__.t()} | {:error, binary()}
+  def unsafe_deserialize(bytes, %WasmexWasmtime.StoreOrCaller{} = store) when is_binary(bytes) do
+    with {:ok, engine} <- WasmexWasmtime.StoreOrCaller.engine(store) do
+      unsafe_deserialize(bytes, engine)
+    end
+  end
 
-  @spec unsafe_deserialize(binary()) :: {:ok, __MODULE__.t()} | {:error, binary()}
-  def unsafe_deserialize(bytes) when is_binary(bytes) do
-    case WasmexWasmtime.Native.module_unsafe_deserialize(bytes) do
+  def unsafe_deserialize(bytes, %WasmexWasmtime.Engine{resource: engine_resource})
+      when is_binary(bytes) do
+    case WasmexWasmtime.Native.module_unsafe_deserialize(bytes, engine_resource) do
       {:ok, resource} -> {:ok, wrap_resource(resource)}
       {:error, err} -> {:error, err}
     end
   end
 
+  @doc """
+  Deserializes a module from a file at the given `path`, e.g. written from the result of
+  `serialize/1` or `WasmexWasmtime.Engine.precompile_module/2`.
+
+  Works like `unsafe_deserialize/2`, but the file is memory mapped instead of being read into
+  the BEAM heap. All safety considerations of `unsafe_deserialize/2` apply. Additionally, the
+  file must not be modified while the module is in use.
+  """
+  @spec unsafe_deserialize_file(
+          Path.t(),
+          WasmexWasmtime.Engine.t() | WasmexWasmtime.StoreOrCaller.t()
+        ) ::
+          {:ok, __MODULE__.t()} | {:error, binary()}
+  def unsafe_deserialize_file(path, %WasmexWasmtime.StoreOrCaller{} = store) do
+    with {:ok, engine} <- WasmexWasmtime.StoreOrCaller.engine(store) do
+      unsafe_deserialize_file(path, engine)
+    end
+  end
+
+  def unsafe_deserialize_file(path, %WasmexWasmtime.Engine{resource: engine_resource}) do
+    case WasmexWasmtime.Native.module_unsafe_deserialize_file(
+           Path.expand(path),
+           engine_resource
+         ) do
+      {:ok, resource} -> {:ok, wrap_resource(resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Checks whether a binary created by `serialize/1` can be deserialized into the given
+  `WasmexWasmtime.Engine` (or the engine of the given store).
+
+  Returns `:ok` if the binary is compatible. Otherwise returns `{:error, {kind, message}}`,
+  with `kind` being one of:
+
+  * `:version` - the binary was serialized by a different wasmtime version
+  * `:target` - the binary was compiled for a different architecture or operating system
+  * `:config` - the binary was compiled with an incompatible `WasmexWasmtime.EngineConfig`
+  * `:invalid` - the binary is not a serialized module
+  * `:incompatible` - any other reason, e.g. a corrupted binary
+
+  This is useful to invalidate stale entries of a cache of serialized modules.
+
+  The check loads the artifact: a compatible binary is fully deserialized into the engine
+  (and dropped again), which costs as much as `unsafe_deserialize/2`. All safety
+  considerations of `unsafe_deserialize/2` apply.
+  """
+  @spec check_compatibility(
+          binary(),
+          WasmexWasmtime.Engine.t() | WasmexWasmtime.StoreOrCaller.t()
+        ) ::
+          :ok | {:error, {:version | :target | :config | :invalid | :incompatible, binary()}}
+  def check_compatibility(bytes, %WasmexWasmtime.StoreOrCaller{} = store) when is_binary(bytes) do
+    with {:ok, engine} <- WasmexWasmtime.StoreOrCaller.engine(store) do
+      check_compatibility(bytes, engine)
+    end
+  end
+
+  def check_compatibility(bytes, %WasmexWasmtime.Engine{resource: engine_resource})
+      when is_binary(bytes) do
+    WasmexWasmtime.Native.module_check_compatibility(engine_resource, bytes)
+  end
+
   defp wrap_resource(resource) do
     %__MODULE__{
       resource: resource,
@@ -11,14 +11,37 @@ defmodule WasmexWasmtime.Native do
     version: version,
     force_build: System.get_env("WASMEX_WASMTIME_BUILD") in ["1", "true"]
 
+  def engine_new(_config), do: error()
+  def engine_precompile_module(_engine_resource, _bytes), do: error()
+
   def module_compile(_store_or_caller_resource, _bytes), do: error()
+  def module_compile_with_engine(_engine_resource, _bytes), do: error()
+  def module_compile_file(_engine_resource, _path), do: error()
   def module_exports(_module_resource), do: error()
   def module_imports(_module_resource), do: error()
   def module_name(_module_resource), do: error()
   def module_serialize(_module_resource), do: error()
-  def module_unsafe_deserialize(_binary), do: error()
+  def module_unsafe_deserialize(_binary, _engine_resource), do: error()
+  def module_unsafe_deserialize_file(_path, _engine_resource), do: error()
+  def module_check_compatibility(_engine_resource, _binary), do: error()
+  def module_validate(_engine_resource, _bytes), do: error()
+
+  def function_call(_store_or_caller_resource, _function_resource, _params, _timeout, _from),
+    do: error()
 
-  def instance_new(_store_or_caller_resource, _module_resource, _imports), do: error()
+  def global_from_instance(_store_or_caller_resource, _instance_resource, _name), do: error()
+  def global_get(_store_or_caller_resource, _global_resource), do: error()
+  def global_set(_store_or_caller_resource, _global_resource, _value), do: error()
+  def global_type(_store_or_caller_resource, _global_resource), do: error()
+
+  def instance_new(
+        _store_or_caller_resource,
+        _module_resource,
+        _imports,
+        _callback_timeout,
+        _callback_pid
+      ),
+      do: error()
 
   def instance_function_export_exists(
         _store_or_caller_resource,
@@ -27,18 +50,22 @@ defmodule WasmexWasmtime.Native do
       ),
       do: error()
 
-  def instance_receive_callback_result(_callback_token, _success, _params), do: error()
+  def instance_receive_callback_result(_callback_token, _success, _result), do: error()
 
   def instance_call_exported_function(
         _store_or_caller_resource,
         _instance_resource,
         _function_name,
         _params,
+        _timeout,
         _from
       ),
       do: error()
 
+  def memory_new(_store_or_caller_resource, _memory_type), do: error()
+  def memory_export_names(_store_resource, _instance_resource), do: error()
   def memory_from_instance(_store_resource, _memory_resource), do: error()
+  def memory_from_instance_by_name(_store_resource, _instance_resource, _name), do: error()
   def memory_bytes_per_element(_size), do: error()
   def memory_length(_store_resource, _memory_resource), do: error()
   def memory_grow(_store_resource, _memory_resource, _pages), do: error()
@@ -53,8 +80,22 @@ defmodule WasmexWasmtime.Native do
   def pipe_read_binary(_pipe_resource), do: error()
   def pipe_write_binary(_pipe_resource, _binary), do: error()
 
-  def store_new(), do: error()
-  def store_new_wasi(_opts), do: error()
+  def store_new(_store_limits, _engine_resource), do: error()
+  def store_or_caller_add_fuel(_store_or_caller_resource, _fuel), do: error()
+  def store_or_caller_cancel_call(_store_or_caller_resource, _from), do: error()
+  def store_or_caller_engine(_store_or_caller_resource), do: error()
+  def store_or_caller_epoch_interruption(_store_or_caller_resource), do: error()
+  def store_or_caller_fuel_consumed(_store_or_caller_resource), do: error()
+  def store_or_caller_fuel_remaining(_store_or_caller_resource), do: error()
+  def store_new_wasi(_opts, _store_limits, _engine_resource), do: error()
+
+  def table_from_instance(_store_or_caller_resource, _instance_resource, _name), do: error()
+  def table_get(_store_or_caller_resource, _table_resource, _index), do: error()
+  def table_grow(_store_or_caller_resource, _table_resource, _delta, _init), do: error()
+  def table_new(_store_or_caller_resource, _table_type), do: error()
+  def table_set(_store_or_caller_resource, _table_resource, _index, _value), do: error()
+  def table_size(_store_or_caller_resource, _table_resource), do: error()
+  def table_type(_store_or_caller_resource, _table_resource), do: error()
 
   # When the NIF is loaded, it will override functions in this module.
   # Calling error is handles the case when the nif could not be loaded.
@@ -3,26 +3,49 @@ defmodule WasmexWasmtime.Store do
   TBD
   """
 
+  alias WasmexWasmtime.Engine
+  alias WasmexWasmtime.StoreLimits
   alias WasmexWasmtime.StoreOrCaller
   alias WasmexWasmtime.Wasi.WasiOptions
 
+  @type options :: [engine: Engine.t(), limits: StoreLimits.t()]
+
   @doc """
-  TBD
+  Creates a new store.
+
+  ## Options
+
+    * `:engine` - the `WasmexWasmtime.Engine` the store belongs to. Modules compiled with the
+      same engine can be instantiated in this store. Uses a new default engine if none is given.
+    * `:limits` - `WasmexWasmtime.StoreLimits` restricting the resources WebAssembly running
+      in the store may use.
+
+  For example:
+
+      {:ok, store} = WasmexWasmtime.Store.new(limits: %WasmexWasmtime.StoreLimits{memory_size: 1_000_000})
   """
-  @spec new() :: {:error, reason :: binary()} | {:ok, StoreOrCaller.t()}
-  def new() do
-    case WasmexWasmtime.Native.store_new() do
+  @spec new(options()) :: {:error, reason :: binary()} | {:ok, StoreOrCaller.t()}
+  def new(opts \\ []) do
+    %Engine{resource: engine_resource} = Keyword.get(opts, :engine) || Engine.new_default()
+
+    case WasmexWasmtime.Native.store_new(Keyword.get(opts, :limits), engine_resource) do
       {:ok, resource} -> {:ok, StoreOrCaller.wrap_resource(resource)}
       {:error, err} -> {:error, err}
     end
   end
 
   @doc """
-  TBD
+  Creates a new store with WASI support.
+
+  Accepts the same `:engine` and `:limits` options as `new/1`.
   """
-  @spec new_wasi(WasiOptions.t()) :: {:error, reason :: binary()} | {:ok, StoreOrCaller.t()}
-  def new_wasi(%WasiOptions{} = options) do
-    case WasmexWasmtime.Native.store_new_wasi(options) do
+  @spec new_wasi(WasiOptions.t(), options()) ::
+          {:error, reason :: binary()} | {:ok, StoreOrCaller.t()}
+  def new_wasi(%WasiOptions{} = options, opts \\ []) do
+    %Engine{resource: engine_resource} = Keyword.get(opts, :engine) || Engine.new_default()
+    store_limits = Keyword.get(opts, :limits)
+
+    case WasmexWasmtime.Native.store_new_wasi(options, store_limits, engine_resource) do
       {:ok, resource} -> {:ok, StoreOrCaller.wrap_resource(resource)}
       {:error, err} -> {:error, err}
     end
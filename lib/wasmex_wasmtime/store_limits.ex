@@ -0,0 +1,79 @@
+defmodule WasmexWasmtime.StoreLimits do
+  @moduledoc ~S"""
+  Limits the resources WebAssembly running in a store may use.
+
+  Pass limits to `WasmexWasmtime.Store.new/1`:
+
+      limits = %WasmexWasmtime.StoreLimits{memory_size: 10 * 65_536, instances: 1}
+      {:ok, store} = WasmexWasmtime.Store.new(limits: limits)
+
+  All limits default to `nil`, which keeps the wasmtime default.
+
+  Growing memories or tables beyond a limit fails. WebAssembly sees a failed
+  `memory.grow` or `table.grow` (returning `-1`) and decides how to continue.
+  Growing them from elixir (e.g. `WasmexWasmtime.Memory.grow/3`) returns
+  `{:error, {:resource_limit_exceeded, :memory}}` or `{:error, {:resource_limit_exceeded, :table}}`.
+  Instantiating a module beyond a limit returns `{:error, {:resource_limit_exceeded, kind}}`
+  with `kind` being one of `:memory`, `:table`, `:instances`, `:tables`, or `:memories`.
+
+  ## Growth Handler
+
+  Besides static limits, memory and table growth can be decided at runtime by an elixir
+  process, the `:growth_handler`. For every growth within the static limits requested by
+  running WebAssembly, the handler receives a message and the WebAssembly call blocks until
+  the handler responds with `respond_growth/2`:
+
+      receive do
+        {:memory_growing, %{current: current, desired: desired, maximum: maximum}, token} ->
+          WasmexWasmtime.StoreLimits.respond_growth(token, desired <= quota)
+
+        {:table_growing, %{current: current, desired: desired, maximum: maximum}, token} ->
+          WasmexWasmtime.StoreLimits.respond_growth(token, true)
+      end
+
+  The handler has to respond within the `:growth_timeout`, growth is denied otherwise.
+
+  Memory sizes are given in bytes, table sizes in elements. `maximum` is the maximum
+  declared by the module or `nil`. Growth happening outside of calls (e.g. when
+  instantiating a module or with `WasmexWasmtime.Memory.grow/3`) is only checked
+  against the static limits.
+
+  ## Options
+
+    * `:memory_size` - the maximum size in bytes of each linear memory.
+    * `:table_elements` - the maximum number of elements of each table.
+    * `:instances` - the maximum number of instances in the store.
+    * `:tables` - the maximum number of tables in the store.
+    * `:memories` - the maximum number of linear memories in the store.
+    * `:growth_handler` - a pid asked to allow or deny memory and table growth, see above.
+    * `:growth_timeout` - milliseconds the growth handler has to respond within. Waits
+      forever if `nil`.
+  """
+
+  defstruct memory_size: nil,
+            table_elements: nil,
+            instances: nil,
+            tables: nil,
+            memories: nil,
+            growth_handler: nil,
+            growth_timeout: nil
+
+  @type t :: %__MODULE__{
+          memory_size: non_neg_integer() | nil,
+          table_elements: non_neg_integer() | nil,
+          instances: non_neg_integer() | nil,
+          tables: non_neg_integer() | nil,
+          memories: non_neg_integer() | nil,
+          growth_handler: pid() | nil,
+          growth_timeout: non_neg_integer() | nil
+        }
+
+  @doc """
+  Responds to a `:memory_growing` or `:table_growing` request sent to the growth handler.
+  """
+  @spec respond_growth(reference(), boolean()) :: :ok
+  def respond_growth(token, allowed) when is_boolean(allowed) do
+    result = if allowed, do: 1, else: 0
+    WasmexWasmtime.Native.instance_receive_callback_result(token, true, [result])
+  end
+end
@@ -22,6 +22,83 @@ defmodule WasmexWasmtime.StoreOrCaller do
       reference: make_ref()
     }
   end
+
+  @doc """
+  Returns the `WasmexWasmtime.Engine` the store was created from.
+  """
+  @spec engine(t()) :: {:ok, WasmexWasmtime.Engine.t()} | {:error, binary()}
+  def engine(%__MODULE__{resource: resource}) do
+    case WasmexWasmtime.Native.store_or_caller_engine(resource) do
+      {:ok, engine_resource} -> {:ok, WasmexWasmtime.Engine.wrap_resource(engine_resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Adds fuel to the store.
+
+  Requires `consume_fuel: true` in the `WasmexWasmtime.EngineConfig` of the store's engine.
+  WebAssembly execution consumes fuel and traps with `{:error, :out_of_fuel}` once
+  there is none left.
+
+      {:ok, engine} = WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{consume_fuel: true})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      :ok = WasmexWasmtime.StoreOrCaller.add_fuel(store, 10_000)
+  """
+  @spec add_fuel(t(), non_neg_integer()) :: :ok | {:error, binary()}
+  def add_fuel(%__MODULE__{resource: resource}, fuel) do
+    WasmexWasmtime.Native.store_or_caller_add_fuel(resource, fuel)
+  end
+
+  @doc """
+  Returns the amount of fuel left in the store.
+
+  Returns an error if fuel consumption is not enabled for the store's engine.
+  """
+  @spec fuel_remaining(t()) :: {:ok, non_neg_integer()} | {:error, binary()}
+  def fuel_remaining(%__MODULE__{resource: resource}) do
+    case WasmexWasmtime.Native.store_or_caller_fuel_remaining(resource) do
+      {:error, err} -> {:error, err}
+      fuel -> {:ok, fuel}
+    end
+  end
+
+  @doc """
+  Returns the amount of fuel consumed by the store so far.
+
+  Returns an error if fuel consumption is not enabled for the store's engine.
+  """
+  @spec fuel_consumed(t()) :: {:ok, non_neg_integer()} | {:error, binary()}
+  def fuel_consumed(%__MODULE__{resource: resource}) do
+    case WasmexWasmtime.Native.store_or_caller_fuel_consumed(resource) do
+      {:error, err} -> {:error, err}
+      fuel -> {:ok, fuel}
+    end
+  end
+
+  @doc """
+  Returns whether the store's engine was configured with `epoch_interruption: true`,
+  which is required for call timeouts and `cancel_call/2`.
+  """
+  @spec epoch_interruption?(t()) :: boolean()
+  def epoch_interruption?(%__MODULE__{resource: resource}) do
+    WasmexWasmtime.Native.store_or_caller_epoch_interruption(resource)
+  end
+
+  @doc """
+  Cancels a WebAssembly call of the store.
+
+  Without `from`, the call currently running in the store is cancelled. Otherwise the call
+  started with the given `from` (see `WasmexWasmtime.Instance.call_exported_function/6`) is
+  cancelled, even if it waits for a call running before it.
+  The call is interrupted on the next epoch tick and results in `{:error, :cancelled}`.
+  Cancelling a call which already returned, or while no call is running, has no effect.
+  Requires `epoch_interruption: true` in the `WasmexWasmtime.EngineConfig` of the store's engine.
+  """
+  @spec cancel_call(t(), term()) :: :ok | {:error, binary()}
+  def cancel_call(%__MODULE__{resource: resource}, from \\ nil) do
+    WasmexWasmtime.Native.store_or_caller_cancel_call(resource, from)
+  end
 end
 
 defimpl Inspect, for: WasmexWasmtime.StoreOrCaller do
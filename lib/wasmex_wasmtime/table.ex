@@ -0,0 +1,159 @@
+defmodule WasmexWasmtime.Table do
+  @moduledoc """
+  A WebAssembly table, an array of references (`:func_ref` or `:extern_ref`).
+
+  Tables can be created up front and imported into instances of the same store:
+
+      {:ok, table} = WasmexWasmtime.Table.new(store, %{minimum: 2, type: :func_ref})
+      imports = %{env: %{__indirect_function_table: {:table, table}}}
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, imports)
+
+  Tables exported by an instance can be read and patched at runtime:
+
+      {:ok, table} = WasmexWasmtime.Table.from_instance(store, instance, "__indirect_function_table")
+      {:ok, function} = WasmexWasmtime.Table.get(store, table, 1)
+      :ok = WasmexWasmtime.Table.set(store, table, 2, function)
+
+  Elements of `:func_ref` tables are `WasmexWasmtime.Function` handles, elements of
+  `:extern_ref` tables are arbitrary Elixir terms. `nil` is the null reference.
+  """
+
+  @type t :: %__MODULE__{
+          resource: binary(),
+          reference: reference()
+        }
+
+  @type table_type :: %{
+          required(:minimum) => non_neg_integer(),
+          optional(:maximum) => non_neg_integer() | nil,
+          required(:type) => :func_ref | :extern_ref
+        }
+
+  @type element :: WasmexWasmtime.Function.t() | term() | nil
+
+  defstruct resource: nil,
+            # The actual NIF table resource.
+            # Normally the compiler will happily do stuff like inlining the
+            # resource in attributes. This will convert the resource into an
+            # empty binary with no warning. This will make that harder to
+            # accidentally do.
+            reference: nil
+
+  @doc """
+  Creates a new table in the given store.
+
+  The table type is given as a map of `:minimum` and (optionally) `:maximum` number of
+  elements and the element `:type`. All elements are initialized with null references.
+  """
+  @spec new(WasmexWasmtime.StoreOrCaller.t(), table_type()) :: {:ok, t()} | {:error, binary()}
+  def new(%WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource}, table_type) do
+    case WasmexWasmtime.Native.table_new(store_or_caller_resource, table_type) do
+      {:ok, resource} -> {:ok, wrap_resource(resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Returns the table exported by the given `instance` under the given `name`.
+  """
+  @spec from_instance(WasmexWasmtime.StoreOrCaller.t(), WasmexWasmtime.Instance.t(), binary()) ::
+          {:ok, t()} | {:error, binary()}
+  def from_instance(store_or_caller, instance, name) when is_binary(name) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %WasmexWasmtime.Instance{resource: instance_resource} = instance
+
+    case WasmexWasmtime.Native.table_from_instance(
+           store_or_caller_resource,
+           instance_resource,
+           name
+         ) do
+      {:ok, resource} -> {:ok, wrap_resource(resource)}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Returns the current number of elements of the table.
+  """
+  @spec size(WasmexWasmtime.StoreOrCaller.t(), t()) :: non_neg_integer() | {:error, binary()}
+  def size(%WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource}, %__MODULE__{
+        resource: resource
+      }) do
+    WasmexWasmtime.Native.table_size(store_or_caller_resource, resource)
+  end
+
+  @doc """
+  Returns the type of the table in the shape `WasmexWasmtime.Module.exports/1` reports tables:
+
+      {:table, %{minimum: 2, maximum: 10, type: :func_ref}}
+  """
+  @spec type(WasmexWasmtime.StoreOrCaller.t(), t()) :: {:table, map()} | {:error, binary()}
+  def type(%WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource}, %__MODULE__{
+        resource: resource
+      }) do
+    WasmexWasmtime.Native.table_type(store_or_caller_resource, resource)
+  end
+
+  @doc """
+  Grows the table by `delta` elements, initialized with `init`. Returns the previous size.
+
+  Growing beyond the table maximum or the `WasmexWasmtime.StoreLimits` of the store
+  fails with an error.
+  """
+  @spec grow(WasmexWasmtime.StoreOrCaller.t(), t(), non_neg_integer(), element()) ::
+          non_neg_integer() | {:error, term()}
+  def grow(store_or_caller, table, delta, init \\ nil) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %__MODULE__{resource: resource} = table
+    WasmexWasmtime.Native.table_grow(store_or_caller_resource, resource, delta, init)
+  end
+
+  @doc """
+  Returns the element at `index`.
+
+  Elements of `:func_ref` tables are returned as callable `WasmexWasmtime.Function` handles,
+  elements of `:extern_ref` tables as the Elixir term they wrap. Null references are `nil`.
+  """
+  @spec get(WasmexWasmtime.StoreOrCaller.t(), t(), non_neg_integer()) ::
+          {:ok, element()} | {:error, binary()}
+  def get(store_or_caller, table, index) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %__MODULE__{resource: resource} = table
+
+    case WasmexWasmtime.Native.table_get(store_or_caller_resource, resource, index) do
+      {:func_ref, nil} -> {:ok, nil}
+      {:func_ref, function} -> {:ok, WasmexWasmtime.Function.wrap_resource(function)}
+      {:extern_ref, term} -> {:ok, term}
+      {:error, err} -> {:error, err}
+    end
+  end
+
+  @doc """
+  Sets the element at `index`.
+
+  `:func_ref` tables accept `WasmexWasmtime.Function` handles of the same store,
+  `:extern_ref` tables accept any Elixir term. `nil` sets a null reference.
+  """
+  @spec set(WasmexWasmtime.StoreOrCaller.t(), t(), non_neg_integer(), element()) ::
+          :ok | {:error, binary()}
+  def set(store_or_caller, table, index, value) do
+    %WasmexWasmtime.StoreOrCaller{resource: store_or_caller_resource} = store_or_caller
+    %__MODULE__{resource: resource} = table
+    WasmexWasmtime.Native.table_set(store_or_caller_resource, resource, index, value)
+  end
+
+  def wrap_resource(resource) do
+    %__MODULE__{
+      resource: resource,
+      reference: make_ref()
+    }
+  end
+end
+
+defimpl Inspect, for: WasmexWasmtime.Table do
+  import Inspect.Algebra
+
+  def inspect(dict, opts) do
+    concat(["#WasmexWasmtime.Table<", to_doc(dict.reference, opts), ">"])
+  end
+end
@@ -37,7 +37,52 @@ rustler::atoms! {
     var,
     __type__ = "type",
 
+    // callback context
+    functions,
+    globals,
+
+    // call errors
+    out_of_fuel,
+    timeout,
+    cancelled,
+    resource_limit_exceeded,
+    callback_timeout,
+    callback_abandoned,
+    callback_error,
+    trap,
+    exit,
+
+    // store limits
+    instances,
+    tables,
+    memories,
+
+    // table elements
+    resource,
+
+    // module compatibility
+    version,
+    target,
+    config,
+    invalid,
+    incompatible,
+
+    // wasm proposals
+    simd,
+    reference_types,
+    bulk_memory,
+    multi_memory,
+    multi_value,
+    threads,
+
+    // engine config
+    none,
+    speed,
+    speed_and_size,
+
     // calls to erlang processes
     returned_function_call,
     invoke_callback,
+    memory_growing,
+    table_growing,
 }
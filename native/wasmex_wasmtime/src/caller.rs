@@ -1,37 +1,103 @@
-use once_cell::sync::Lazy;
-use std::{collections::HashMap, sync::Mutex};
+//! Locking of stores and callers.
+//! The `Caller` of an imported function is handed to elixir while its callback runs.
+//! It borrows the store only until the imported function returns, so it is kept behind
+//! the same lock as stores, which rejects the caller once it expired.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError},
+};
+
 use wasmtime::Caller;
 
-use crate::store::StoreData;
+use crate::{environment::StoreOrCaller, store::StoreData};
+
+#[derive(Clone)]
+pub struct StoreOrCallerLock {
+    inner: Arc<Mutex<StoreOrCaller>>,
+}
 
-static GLOBAL_DATA: Lazy<Mutex<HashMap<i32, Caller<StoreData>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+#[derive(Debug)]
+pub enum LockError {
+    Poisoned,
+    WouldBlock,
+    Expired,
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Poisoned => write!(f, "the mutex was poisoned"),
+            LockError::WouldBlock => write!(f, "the resource is in use"),
+            LockError::Expired => write!(f, "the callback of this caller already returned"),
+        }
+    }
+}
+
+impl StoreOrCallerLock {
+    pub(crate) fn new(store_or_caller: StoreOrCaller) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(store_or_caller)),
+        }
+    }
+
+    /// Makes the caller of an imported function available until the returned guard is dropped.
+    ///
+    /// # Safety
+    ///
+    /// The guard must be dropped before the imported function the caller belongs to returns,
+    /// it must not be leaked (e.g. with `std::mem::forget`).
+    pub(crate) unsafe fn from_caller(caller: Caller<'_, StoreData>) -> CallerGuard {
+        let caller =
+            std::mem::transmute::<Caller<'_, StoreData>, Caller<'static, StoreData>>(caller);
+        CallerGuard {
+            lock: Self::new(StoreOrCaller::Caller(caller)),
+        }
+    }
+
+    pub fn lock(&self) -> Result<MutexGuard<'_, StoreOrCaller>, LockError> {
+        let guard = self.inner.lock().map_err(|_| LockError::Poisoned)?;
+        unless_expired(guard)
+    }
+
+    pub fn try_lock(&self) -> Result<MutexGuard<'_, StoreOrCaller>, LockError> {
+        let guard = self.inner.try_lock().map_err(|err| match err {
+            TryLockError::Poisoned(_) => LockError::Poisoned,
+            TryLockError::WouldBlock => LockError::WouldBlock,
+        })?;
+        unless_expired(guard)
+    }
+
+    // Waits until the caller is not in use anymore and drops it.
+    // Later attempts to lock it fail with `LockError::Expired`.
+    fn expire(&self) {
+        let mut guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        *guard = StoreOrCaller::Expired;
+    }
+}
 
-pub(crate) fn get_caller(token: &i32) -> Option<&Caller<'_, StoreData>> {
-    let map = &*(GLOBAL_DATA.lock().unwrap());
-    map.get(token).map(|caller| unsafe {
-        std::mem::transmute::<&Caller<'_, StoreData>, &Caller<'_, StoreData>>(caller)
-    })
+/// Expires the caller of an imported function once dropped, also when unwinding from a panic.
+pub(crate) struct CallerGuard {
+    lock: StoreOrCallerLock,
 }
 
-pub(crate) fn get_caller_mut(token: &i32) -> Option<&mut Caller<'_, StoreData>> {
-    let map = &mut *(GLOBAL_DATA.lock().unwrap());
-    map.get_mut(token).map(|caller| unsafe {
-        std::mem::transmute::<&mut Caller<'_, StoreData>, &mut Caller<'_, StoreData>>(caller)
-    })
+impl CallerGuard {
+    pub(crate) fn lock(&self) -> &StoreOrCallerLock {
+        &self.lock
+    }
 }
 
-pub(crate) fn set_caller(caller: Caller<StoreData>) -> i32 {
-    let mut map = GLOBAL_DATA.lock().unwrap();
-    // TODO: prevent duplicates by throwing the dice again when the id is already known
-    let token = rand::random();
-    let caller =
-        unsafe { std::mem::transmute::<Caller<'_, StoreData>, Caller<'static, StoreData>>(caller) };
-    map.insert(token, caller);
-    token
+impl Drop for CallerGuard {
+    fn drop(&mut self) {
+        self.lock.expire();
+    }
 }
 
-pub(crate) fn remove_caller(token: i32) {
-    let mut map = GLOBAL_DATA.lock().unwrap();
-    map.remove(&token);
+fn unless_expired(
+    guard: MutexGuard<'_, StoreOrCaller>,
+) -> Result<MutexGuard<'_, StoreOrCaller>, LockError> {
+    match *guard {
+        StoreOrCaller::Expired => Err(LockError::Expired),
+        _ => Ok(guard),
+    }
 }
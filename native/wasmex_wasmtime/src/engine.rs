@@ -0,0 +1,155 @@
+//! Engine API of wasmtime.
+//! An engine is the global compilation environment shared by stores and modules.
+
+use rustler::{resource::ResourceArc, types::binary::Binary, Atom, Error, NifResult, OwnedBinary};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use wasmtime::{Config, Engine, OptLevel, WasmBacktraceDetails};
+
+use crate::atoms;
+
+pub struct EngineResource {
+    pub inner: Engine,
+    pub(crate) epoch_ticker: Option<Arc<EpochTicker>>,
+}
+
+// Increments the epoch of an engine in a background thread.
+// Stores created from the engine keep the ticker alive, it stops once the
+// engine resource and all of its stores are gone.
+pub(crate) struct EpochTicker {
+    stopped: Arc<AtomicBool>,
+}
+
+impl EpochTicker {
+    fn start(engine: Engine, interval: Duration) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        thread::spawn(move || {
+            while !thread_stopped.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                engine.increment_epoch();
+            }
+        });
+        Self { stopped }
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(NifTuple)]
+pub struct EngineResourceResponse {
+    pub(crate) ok: rustler::Atom,
+    pub(crate) resource: ResourceArc<EngineResource>,
+}
+
+#[derive(NifStruct)]
+#[module = "WasmexWasmtime.EngineConfig"]
+pub struct ExEngineConfig {
+    consume_fuel: bool,
+    cranelift_opt_level: Atom,
+    epoch_interruption: bool,
+    epoch_tick_interval: u64,
+    parallel_compilation: bool,
+    target: Option<String>,
+    wasm_backtrace_details: bool,
+    wasm_bulk_memory: bool,
+    wasm_memory64: bool,
+    wasm_multi_memory: bool,
+    wasm_multi_value: bool,
+    wasm_reference_types: bool,
+    wasm_simd: bool,
+    wasm_threads: bool,
+}
+
+#[rustler::nif(name = "engine_new")]
+pub fn new(engine_config: ExEngineConfig) -> NifResult<EngineResourceResponse> {
+    let config = wasmtime_config(&engine_config)?;
+    let engine = Engine::new(&config).map_err(|err| Error::Term(Box::new(err.to_string())))?;
+    let epoch_ticker = if engine_config.epoch_interruption {
+        let interval = Duration::from_millis(engine_config.epoch_tick_interval.max(1));
+        Some(Arc::new(EpochTicker::start(engine.clone(), interval)))
+    } else {
+        None
+    };
+    let resource = ResourceArc::new(EngineResource {
+        inner: engine,
+        epoch_ticker,
+    });
+    Ok(EngineResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+#[rustler::nif(name = "engine_precompile_module", schedule = "DirtyCpu")]
+pub fn precompile_module<'a>(
+    env: rustler::Env<'a>,
+    engine_resource: ResourceArc<EngineResource>,
+    binary: Binary,
+) -> NifResult<Binary<'a>> {
+    let engine = &engine_resource.inner;
+    let bytes = wat::parse_bytes(binary.as_slice()).map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Error while parsing bytes: {}.", e)))
+    })?;
+    let serialized_module = engine.precompile_module(&bytes).map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not precompile module: {:?}", e)))
+    })?;
+    let mut binary = OwnedBinary::new(serialized_module.len())
+        .ok_or_else(|| rustler::Error::Term(Box::new("not enough memory")))?;
+    binary.copy_from_slice(&serialized_module);
+    Ok(binary.release(env))
+}
+
+pub(crate) fn wasmtime_config(engine_config: &ExEngineConfig) -> Result<Config, Error> {
+    let cranelift_opt_level = opt_level_from_atom(engine_config.cranelift_opt_level)?;
+    let backtrace_details = if engine_config.wasm_backtrace_details {
+        WasmBacktraceDetails::Enable
+    } else {
+        WasmBacktraceDetails::Disable
+    };
+
+    let mut config = Config::new();
+    config
+        .consume_fuel(engine_config.consume_fuel)
+        .cranelift_opt_level(cranelift_opt_level)
+        .epoch_interruption(engine_config.epoch_interruption)
+        .parallel_compilation(engine_config.parallel_compilation)
+        .wasm_backtrace_details(backtrace_details)
+        .wasm_bulk_memory(engine_config.wasm_bulk_memory)
+        .wasm_memory64(engine_config.wasm_memory64)
+        .wasm_multi_memory(engine_config.wasm_multi_memory)
+        .wasm_multi_value(engine_config.wasm_multi_value)
+        .wasm_reference_types(engine_config.wasm_reference_types)
+        .wasm_simd(engine_config.wasm_simd)
+        .wasm_threads(engine_config.wasm_threads);
+    if let Some(target) = &engine_config.target {
+        config
+            .target(target)
+            .map_err(|err| Error::Term(Box::new(err.to_string())))?;
+    }
+    Ok(config)
+}
+
+fn opt_level_from_atom(atom: Atom) -> Result<OptLevel, Error> {
+    if atoms::none().eq(&atom) {
+        Ok(OptLevel::None)
+    } else if atoms::speed().eq(&atom) {
+        Ok(OptLevel::Speed)
+    } else if atoms::speed_and_size().eq(&atom) {
+        Ok(OptLevel::SpeedAndSize)
+    } else {
+        Err(Error::Term(Box::new(
+            "cranelift_opt_level must be one of :none, :speed, or :speed_and_size",
+        )))
+    }
+}
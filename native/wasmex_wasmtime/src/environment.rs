@@ -1,55 +1,377 @@
-use std::sync::{Condvar, Mutex};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
 
 use rustler::{
-    resource::ResourceArc, types::tuple, Atom, Encoder, Error, ListIterator, MapIterator, OwnedEnv,
-    Term,
+    resource::{ResourceArc, ResourceTypeProvider},
+    types::tuple,
+    Atom, Encoder, Env, Error, ListIterator, LocalPid, MapIterator, OwnedEnv, Term,
 };
 use wasmtime::{
-    AsContext, AsContextMut, Caller, Engine, Extern, FuncType, Linker, Store, Val, ValType,
+    AsContext, AsContextMut, Caller, Engine, Extern, ExternType, Func, FuncType, Global,
+    GlobalType, Linker, Memory, Module, Mutability, SharedMemory, Store, Table, Val, ValType,
 };
-use wiggle::anyhow::{self, anyhow};
+use wiggle::anyhow;
 
 use crate::{
     atoms::{self},
-    caller::{get_caller, get_caller_mut, remove_caller, set_caller},
-    instance::{map_wasm_values_to_vals, WasmValue},
-    memory::MemoryResource,
-    store::StoreData,
+    caller::StoreOrCallerLock,
+    engine::EngineResource,
+    functions::FunctionResource,
+    global::GlobalResource,
+    instance::{decode_function_param_terms, map_wasm_values_to_vals, PendingCall, WasmValue},
+    memory::{ExMemoryType, MemoryResource},
+    store::{CallInterruption, StoreData},
+    table::{decode_table_type, new_table, TableResource},
 };
 
 pub struct CallbackTokenResource {
-    pub token: CallbackToken,
+    pub token: Arc<CallbackToken>,
+}
+
+// The elixir side holds the only reference to the token resource. Once it is dropped
+// without a result (e.g. because the receiving process exited), the waiting call is abandoned.
+impl Drop for CallbackTokenResource {
+    fn drop(&mut self) {
+        self.token.abandon();
+    }
 }
 
 pub struct CallbackToken {
     pub continue_signal: Condvar,
     pub return_types: Vec<ValType>,
-    pub return_values: Mutex<Option<(bool, Vec<WasmValue>)>>,
+    pub return_values: Mutex<Option<CallbackResult>>,
+    // calls made through the caller while the elixir callback runs
+    pub(crate) nested_calls: Mutex<VecDeque<PendingCall>>,
+    abandoned: AtomicBool,
+    // set once the wasm thread stopped waiting for the callback
+    closed: AtomicBool,
+}
+
+/// Where imported functions send their callbacks to, and how long they wait for them.
+#[derive(Clone)]
+pub struct CallbackOptions {
+    pub pid: LocalPid,
+    pub timeout: Option<Duration>,
+}
+
+/// The outcome of an elixir callback, stored by `receive_callback_result`.
+pub enum CallbackResult {
+    Returned(Vec<WasmValue>),
+    Raised(CallbackError),
+    Trapped(CallbackTrap),
+}
+
+/// An exception raised, thrown, or exited with by an elixir callback.
+#[derive(NifMap, Debug, Clone)]
+pub struct CallbackError {
+    pub kind: Atom,
+    pub message: String,
+    pub stacktrace: Option<String>,
+}
+
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the elixir callback failed: {}", self.message)
+    }
+}
+
+impl std::error::Error for CallbackError {}
+
+/// A trap deliberately requested by an elixir callback to abort the WebAssembly call.
+#[derive(Debug, Clone)]
+pub enum CallbackTrap {
+    Trap(String),
+    Exit(i32),
+}
+
+impl fmt::Display for CallbackTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackTrap::Trap(reason) => write!(f, "the elixir callback trapped: {}", reason),
+            CallbackTrap::Exit(code) => {
+                write!(f, "the elixir callback exited with code {}", code)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CallbackTrap {}
+
+/// Why an elixir callback did not return a result.
+#[derive(Debug, Clone, Copy)]
+pub enum CallbackFailure {
+    Timeout(Duration),
+    Abandoned,
+}
+
+impl fmt::Display for CallbackFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackFailure::Timeout(timeout) => write!(
+                f,
+                "the elixir callback did not return within {} ms",
+                timeout.as_millis()
+            ),
+            CallbackFailure::Abandoned => write!(
+                f,
+                "the elixir callback was abandoned, the receiving process exited"
+            ),
+        }
+    }
 }
 
-pub fn link_imports(linker: &mut Linker<StoreData>, imports: MapIterator) -> Result<(), Error> {
+impl std::error::Error for CallbackFailure {}
+
+impl CallbackToken {
+    pub(crate) fn new(return_types: Vec<ValType>) -> Self {
+        Self {
+            continue_signal: Condvar::new(),
+            return_types,
+            return_values: Mutex::new(None),
+            nested_calls: Mutex::new(VecDeque::new()),
+            abandoned: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn abandon(&self) {
+        // hold the lock, so that the waiting thread does not miss the signal
+        let _result = self.return_values.lock().unwrap();
+        self.abandoned.store(true, Ordering::SeqCst);
+        self.continue_signal.notify_one();
+    }
+
+    // Queues a call to be run by the wasm thread waiting for this callback, which is the
+    // only thread allowed to use the caller. Fails once the wasm thread stopped waiting,
+    // since the caller is gone then.
+    pub(crate) fn queue_nested_call(&self, call: PendingCall) -> Result<(), Error> {
+        let _result = self.return_values.lock().unwrap();
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(Error::Term(Box::new(
+                "Could not call function: the callback of this caller already returned",
+            )));
+        }
+        self.nested_calls.lock().unwrap().push_back(call);
+        self.continue_signal.notify_one();
+        Ok(())
+    }
+
+    // Waits until `receive_callback_result` stored the callback result.
+    // Nested calls are run in the meantime, all of them before the callback result is used.
+    // Gives up once the timeout passed or the elixir side dropped the token.
+    pub(crate) fn wait_for_result(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<MutexGuard<'_, Option<CallbackResult>>, CallbackFailure> {
+        let deadline = timeout.map(|timeout| (Instant::now() + timeout, timeout));
+        let mut result = self.return_values.lock().unwrap();
+        let outcome = loop {
+            let nested_call = self.nested_calls.lock().unwrap().pop_front();
+            if let Some(call) = nested_call {
+                drop(result);
+                call.run();
+                result = self.return_values.lock().unwrap();
+                continue;
+            }
+            if result.is_some() {
+                break Ok(());
+            }
+            if self.abandoned.load(Ordering::SeqCst) {
+                break Err(CallbackFailure::Abandoned);
+            }
+            result = match deadline {
+                None => self.continue_signal.wait(result).unwrap(),
+                Some((deadline, timeout)) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break Err(CallbackFailure::Timeout(timeout));
+                    }
+                    let (result, _) = self
+                        .continue_signal
+                        .wait_timeout(result, deadline - now)
+                        .unwrap();
+                    result
+                }
+            };
+        };
+        self.closed.store(true, Ordering::SeqCst);
+        outcome.map(|_| result)
+    }
+}
+
+pub fn link_imports(
+    linker: &mut Linker<StoreData>,
+    store_or_caller: &mut StoreOrCaller,
+    module: &Module,
+    imports: MapIterator,
+    callback_options: CallbackOptions,
+) -> Result<(), Error> {
+    let export_names = ExportNames::new(module);
     for (namespace_name, namespace_definition) in imports {
         let namespace_name = namespace_name.decode::<String>()?;
         let definition: MapIterator = namespace_definition.decode()?;
 
         for (import_name, import) in definition {
             let import_name = import_name.decode::<String>()?;
-            link_import(linker, &namespace_name, &import_name, import)?;
+            link_import(
+                linker,
+                store_or_caller,
+                &export_names,
+                &namespace_name,
+                &import_name,
+                import,
+                callback_options.clone(),
+            )?;
         }
     }
     Ok(())
 }
 
+// Names of the exports of the module by kind. Callbacks can only look up the exports
+// of the calling instance by name. The memory called "memory" comes first.
+#[derive(Clone)]
+struct ExportNames {
+    functions: Vec<String>,
+    globals: Vec<String>,
+    memories: Vec<String>,
+    tables: Vec<String>,
+}
+
+impl ExportNames {
+    fn new(module: &Module) -> Self {
+        let mut names = Self {
+            functions: vec![],
+            globals: vec![],
+            memories: vec![],
+            tables: vec![],
+        };
+        for export in module.exports() {
+            let name = export.name().to_string();
+            match export.ty() {
+                ExternType::Func(_) => names.functions.push(name),
+                ExternType::Global(_) => names.globals.push(name),
+                ExternType::Memory(_) => names.memories.push(name),
+                ExternType::Table(_) => names.tables.push(name),
+            }
+        }
+        names.memories.sort_by_key(|name| name != "memory");
+        names
+    }
+}
+
+// The exports of the calling instance, looked up while the caller is available.
+struct CallerExports<'a> {
+    functions: Vec<(&'a str, Func)>,
+    globals: Vec<(&'a str, Global)>,
+    memories: Vec<(&'a str, Memory)>,
+    tables: Vec<(&'a str, Table)>,
+}
+
+impl<'a> CallerExports<'a> {
+    fn new(caller: &mut Caller<'_, StoreData>, names: &'a ExportNames) -> Self {
+        let mut exports = Self {
+            functions: vec![],
+            globals: vec![],
+            memories: vec![],
+            tables: vec![],
+        };
+        for name in &names.functions {
+            if let Some(Extern::Func(func)) = caller.get_export(name) {
+                exports.functions.push((name.as_str(), func));
+            }
+        }
+        for name in &names.globals {
+            if let Some(Extern::Global(global)) = caller.get_export(name) {
+                exports.globals.push((name.as_str(), global));
+            }
+        }
+        for name in &names.memories {
+            if let Some(Extern::Memory(memory)) = caller.get_export(name) {
+                exports.memories.push((name.as_str(), memory));
+            }
+        }
+        for name in &names.tables {
+            if let Some(Extern::Table(table)) = caller.get_export(name) {
+                exports.tables.push((name.as_str(), table));
+            }
+        }
+        exports
+    }
+
+    // instances without an exported memory get `nil` as memory
+    fn memory(&self) -> Option<Memory> {
+        self.memories.first().map(|(_name, memory)| *memory)
+    }
+
+    // Adds the exports to the callback context, each kind as a map of export name to resource.
+    fn put_into<'b>(&self, env: Env<'b>, callback_context: Term<'b>) -> Term<'b> {
+        let entries = [
+            (
+                atoms::functions(),
+                exports_map(env, &self.functions, |func| FunctionResource {
+                    inner: Mutex::new(func),
+                }),
+            ),
+            (
+                atoms::globals(),
+                exports_map(env, &self.globals, |global| GlobalResource {
+                    inner: Mutex::new(global),
+                }),
+            ),
+            (
+                atoms::memories(),
+                exports_map(env, &self.memories, |memory| MemoryResource {
+                    inner: Mutex::new(memory),
+                }),
+            ),
+            (
+                atoms::tables(),
+                exports_map(env, &self.tables, |table| TableResource {
+                    inner: Mutex::new(table),
+                }),
+            ),
+        ];
+        entries
+            .into_iter()
+            .fold(callback_context, |context, (key, value)| {
+                Term::map_put(context, key.encode(env), value).unwrap()
+            })
+    }
+}
+
+fn exports_map<'a, T: Copy, R: ResourceTypeProvider>(
+    env: Env<'a>,
+    exports: &[(&str, T)],
+    resource: impl Fn(T) -> R,
+) -> Term<'a> {
+    exports
+        .iter()
+        .fold(Term::map_new(env), |map, (name, export)| {
+            let export_resource = ResourceArc::new(resource(*export));
+            Term::map_put(map, name.encode(env), export_resource.encode(env)).unwrap()
+        })
+}
+
 fn link_import(
     linker: &mut Linker<StoreData>,
+    store_or_caller: &mut StoreOrCaller,
+    export_names: &ExportNames,
     namespace_name: &str,
     import_name: &str,
     definition: Term,
+    callback_options: CallbackOptions,
 ) -> Result<(), Error> {
     let import_tuple = tuple::get_tuple(definition)?;
 
     let import_type = import_tuple
-        .get(0)
+        .first()
         .ok_or(Error::Atom("missing_import_type"))?;
     let import_type =
         Atom::from_term(*import_type).map_err(|_| Error::Atom("import type must be an atom"))?;
@@ -57,22 +379,167 @@ fn link_import(
     if atoms::__fn__().eq(&import_type) {
         return link_imported_function(
             linker,
+            export_names.clone(),
             namespace_name.to_string(),
             import_name.to_string(),
             definition,
+            callback_options,
+        );
+    }
+    if atoms::global().eq(&import_type) {
+        return link_imported_global(
+            linker,
+            store_or_caller,
+            namespace_name,
+            import_name,
+            &import_tuple,
+        );
+    }
+
+    if atoms::memory().eq(&import_type) {
+        return link_imported_memory(
+            linker,
+            store_or_caller,
+            namespace_name,
+            import_name,
+            &import_tuple,
+        );
+    }
+
+    if atoms::table().eq(&import_type) {
+        return link_imported_table(
+            linker,
+            store_or_caller,
+            namespace_name,
+            import_name,
+            &import_tuple,
         );
     }
 
     Err(Error::Atom("unknown import type"))
 }
 
+// Defines a table from an import definition, which is either an existing table resource
+// or a table type like `{:table, %{minimum: 1, maximum: 2, type: :func_ref}}`.
+fn link_imported_table(
+    linker: &mut Linker<StoreData>,
+    store_or_caller: &mut StoreOrCaller,
+    namespace_name: &str,
+    import_name: &str,
+    import_tuple: &[Term],
+) -> Result<(), Error> {
+    let definition = import_tuple
+        .get(1)
+        .ok_or(Error::Atom("missing_table_definition"))?;
+
+    let table = match definition.decode::<ResourceArc<TableResource>>() {
+        Ok(table_resource) => {
+            *(table_resource.inner.lock().map_err(|e| {
+                Error::Term(Box::new(format!("Could not unlock table resource: {}", e)))
+            })?)
+        }
+        Err(_) => new_table(store_or_caller, decode_table_type(*definition)?)?,
+    };
+    linker
+        .define(namespace_name, import_name, table)
+        .map_err(|err| Error::Term(Box::new(err.to_string())))?;
+    Ok(())
+}
+
+// Defines a memory from an import definition, which is either an existing memory resource
+// or a memory type like `{:memory, %{minimum: 1, maximum: 2, shared: false}}`.
+fn link_imported_memory(
+    linker: &mut Linker<StoreData>,
+    store_or_caller: &mut StoreOrCaller,
+    namespace_name: &str,
+    import_name: &str,
+    import_tuple: &[Term],
+) -> Result<(), Error> {
+    let definition = import_tuple
+        .get(1)
+        .ok_or(Error::Atom("missing_memory_definition"))?;
+
+    let memory: Extern = match definition.decode::<ResourceArc<MemoryResource>>() {
+        Ok(memory_resource) => {
+            let memory = memory_resource.inner.lock().map_err(|e| {
+                Error::Term(Box::new(format!("Could not unlock memory resource: {}", e)))
+            })?;
+            (*memory).into()
+        }
+        Err(_) => {
+            let memory_type: ExMemoryType = definition.decode()?;
+            let ty = memory_type.to_wasmtime()?;
+            if memory_type.shared {
+                SharedMemory::new(store_or_caller.engine(), ty)
+                    .map_err(|err| Error::Term(Box::new(err.to_string())))?
+                    .into()
+            } else {
+                Memory::new(store_or_caller, ty)
+                    .map_err(|err| Error::Term(Box::new(err.to_string())))?
+                    .into()
+            }
+        }
+    };
+    linker
+        .define(namespace_name, import_name, memory)
+        .map_err(|err| Error::Term(Box::new(err.to_string())))?;
+    Ok(())
+}
+
+// Defines a global from an import definition like `{:global, :i32, 42, :const}`.
+fn link_imported_global(
+    linker: &mut Linker<StoreData>,
+    store_or_caller: &mut StoreOrCaller,
+    namespace_name: &str,
+    import_name: &str,
+    import_tuple: &[Term],
+) -> Result<(), Error> {
+    let value_type = import_tuple
+        .get(1)
+        .ok_or(Error::Atom("missing_global_type"))?;
+    let value = import_tuple
+        .get(2)
+        .ok_or(Error::Atom("missing_global_value"))?;
+    let mutability = import_tuple
+        .get(3)
+        .ok_or(Error::Atom("missing_global_mutability"))?;
+
+    let value_type = term_to_arg_type(*value_type)?;
+    let value = decode_function_param_terms(std::slice::from_ref(&value_type), vec![*value])
+        .map_err(|reason| {
+            Error::Term(Box::new(format!(
+                "invalid value for global `{}`: {}",
+                import_name, reason
+            )))
+        })?;
+    let value = map_wasm_values_to_vals(&value).remove(0);
+    let mutability = term_to_mutability(*mutability)?;
+
+    let global = Global::new(
+        store_or_caller,
+        GlobalType::new(value_type, mutability),
+        value,
+    )
+    .map_err(|err| Error::Term(Box::new(err.to_string())))?;
+    linker
+        .define(namespace_name, import_name, global)
+        .map_err(|err| Error::Term(Box::new(err.to_string())))?;
+    Ok(())
+}
+
 pub enum StoreOrCaller {
     Store(Store<StoreData>),
-    Caller(i32),
+    Caller(Caller<'static, StoreData>),
+    // a caller whose imported function returned, it can not be locked anymore
+    Expired,
 }
 
 pub struct StoreOrCallerResource {
-    pub inner: Mutex<StoreOrCaller>,
+    pub inner: StoreOrCallerLock,
+    pub(crate) interruption: Arc<CallInterruption>,
+    pub(crate) engine: ResourceArc<EngineResource>,
+    // set for callers, calls through them are run by the wasm thread waiting for the callback
+    pub(crate) callback_token: Option<ResourceArc<CallbackTokenResource>>,
 }
 
 #[derive(NifTuple)]
@@ -85,14 +552,16 @@ impl StoreOrCaller {
     pub(crate) fn engine(&self) -> &Engine {
         match self {
             StoreOrCaller::Store(store) => store.engine(),
-            StoreOrCaller::Caller(token) => get_caller(token).unwrap().engine(),
+            StoreOrCaller::Caller(caller) => caller.engine(),
+            StoreOrCaller::Expired => unreachable!("expired callers can not be locked"),
         }
     }
 
     pub(crate) fn data(&self) -> &StoreData {
         match self {
             StoreOrCaller::Store(store) => store.data(),
-            StoreOrCaller::Caller(token) => get_caller(token).unwrap().data(),
+            StoreOrCaller::Caller(caller) => caller.data(),
+            StoreOrCaller::Expired => unreachable!("expired callers can not be locked"),
         }
     }
 }
@@ -103,7 +572,8 @@ impl AsContext for StoreOrCaller {
     fn as_context(&self) -> wasmtime::StoreContext<'_, Self::Data> {
         match self {
             StoreOrCaller::Store(store) => store.as_context(),
-            StoreOrCaller::Caller(token) => get_caller(token).unwrap().as_context(),
+            StoreOrCaller::Caller(caller) => caller.as_context(),
+            StoreOrCaller::Expired => unreachable!("expired callers can not be locked"),
         }
     }
 }
@@ -112,7 +582,8 @@ impl AsContextMut for StoreOrCaller {
     fn as_context_mut(&mut self) -> wasmtime::StoreContextMut<'_, Self::Data> {
         match self {
             StoreOrCaller::Store(store) => store.as_context_mut(),
-            StoreOrCaller::Caller(token) => get_caller_mut(token).unwrap().as_context_mut(),
+            StoreOrCaller::Caller(caller) => caller.as_context_mut(),
+            StoreOrCaller::Expired => unreachable!("expired callers can not be locked"),
         }
     }
 }
@@ -127,16 +598,26 @@ impl AsContextMut for StoreOrCaller {
 // 5. after the callback finished execution, return values are send back to Rust via `receive_callback_result`
 // 6. `receive_callback_result` saves the return values in the callback tokens mutex and signals the condvar,
 //    so that the original wrapper function can continue code execution
+// While waiting, the wrapper runs calls the elixir callback makes through its caller (see `PendingCall`),
+// so that the caller is only ever used on this thread and while it is valid.
+// The wrapper traps when the callback does not return within the callback timeout, or when the
+// receiving process exits before returning a result.
 fn link_imported_function(
     linker: &mut Linker<StoreData>,
+    export_names: ExportNames,
     namespace_name: String,
     import_name: String,
     definition: Term,
+    callback_options: CallbackOptions,
 ) -> Result<(), Error> {
-    let pid = definition.get_env().pid();
-
     let import_tuple = tuple::get_tuple(definition)?;
 
+    let pid = match import_tuple.get(4) {
+        Some(pid_term) => pid_term.decode::<LocalPid>()?,
+        None => callback_options.pid,
+    };
+    let callback_timeout = callback_options.timeout;
+
     let param_term = import_tuple
         .get(1)
         .ok_or(Error::Atom("missing_import_params"))?;
@@ -164,20 +645,17 @@ fn link_imported_function(
                   params: &[Val],
                   results: &mut [Val]|
                   -> Result<(), anyhow::Error> {
+                let token = Arc::new(CallbackToken::new(results_signature.clone()));
                 let callback_token = ResourceArc::new(CallbackTokenResource {
-                    token: CallbackToken {
-                        continue_signal: Condvar::new(),
-                        return_types: results_signature.clone(),
-                        return_values: Mutex::new(None),
-                    },
+                    token: token.clone(),
                 });
 
-                let memory = match caller.get_export("memory") {
-                    Some(Extern::Memory(mem)) => mem,
-                    _ => return Err(anyhow!("failed to find host memory")),
-                };
+                let exports = CallerExports::new(&mut caller, &export_names);
 
-                let caller_token = set_caller(caller);
+                let interruption = caller.data().interruption.clone();
+                let engine = caller.data().engine.clone();
+                // Safety: the guard expires the caller when dropped, before this function returns.
+                let caller_guard = unsafe { StoreOrCallerLock::from_caller(caller) };
 
                 let mut msg_env = OwnedEnv::new();
                 msg_env.send_and_clear(&pid.clone(), |env| {
@@ -200,22 +678,27 @@ fn link_imported_function(
                             }
                         })
                     }
-                    // Callback context will contain memory (plus maybe globals, tables etc later).
+                    // Callback context contains the memory, and all exported functions, globals,
+                    // memories, and tables of the calling instance.
                     // This will allow Elixir callback to operate on these objects.
-                    let callback_context = Term::map_new(env);
+                    let callback_context = exports.put_into(env, Term::map_new(env));
 
-                    let memory_resource = ResourceArc::new(MemoryResource {
-                        inner: Mutex::new(memory),
-                    });
-                    let callback_context = Term::map_put(
-                        callback_context,
-                        atoms::memory().encode(env),
-                        memory_resource.encode(env),
-                    )
-                    .unwrap();
+                    let memory_term = match exports.memory() {
+                        Some(memory) => ResourceArc::new(MemoryResource {
+                            inner: Mutex::new(memory),
+                        })
+                        .encode(env),
+                        None => atoms::__nil__().encode(env),
+                    };
+                    let callback_context =
+                        Term::map_put(callback_context, atoms::memory().encode(env), memory_term)
+                            .unwrap();
 
                     let caller_resource = ResourceArc::new(StoreOrCallerResource {
-                        inner: Mutex::new(StoreOrCaller::Caller(caller_token)),
+                        inner: caller_guard.lock().clone(),
+                        interruption,
+                        engine,
+                        callback_token: Some(callback_token.clone()),
                     });
 
                     let callback_context = Term::map_put(
@@ -224,6 +707,15 @@ fn link_imported_function(
                         caller_resource.encode(env),
                     )
                     .unwrap();
+
+                    // calls from within the callback wait for their result no longer than this
+                    let timeout = callback_timeout.map(|timeout| timeout.as_millis() as u64);
+                    let callback_context = Term::map_put(
+                        callback_context,
+                        atoms::callback_timeout().encode(env),
+                        timeout.encode(env),
+                    )
+                    .unwrap();
                     (
                         atoms::invoke_callback(),
                         namespace_name.clone(),
@@ -235,19 +727,23 @@ fn link_imported_function(
                         .encode(env)
                 });
 
+                // Only the elixir side may keep the token resource, see `CallbackTokenResource`.
+                drop(callback_token);
+
                 // Wait for the thread to start up - `receive_callback_result` is responsible for that.
-                let mut result = callback_token.token.return_values.lock().unwrap();
-                while result.is_none() {
-                    result = callback_token.token.continue_signal.wait(result).unwrap();
-                }
-                remove_caller(caller_token);
+                let result = token.wait_for_result(callback_timeout);
+                drop(caller_guard);
 
-                let result: &(bool, Vec<WasmValue>) = result
+                let result = result?;
+                match result
                     .as_ref()
-                    .expect("expect callback token to contain a result");
-                match result {
-                    (true, return_values) => write_results(results, return_values),
-                    (false, _) => Err(anyhow!("the elixir callback threw an exception")),
+                    .expect("expect callback token to contain a result")
+                {
+                    CallbackResult::Returned(return_values) => {
+                        write_results(results, return_values)
+                    }
+                    CallbackResult::Raised(error) => Err(error.clone().into()),
+                    CallbackResult::Trapped(trap) => Err(trap.clone().into()),
                 }
             },
         )
@@ -261,6 +757,14 @@ fn write_results(results: &mut [Val], return_values: &[WasmValue]) -> Result<(),
     Ok(())
 }
 
+fn term_to_mutability(term: Term) -> Result<Mutability, Error> {
+    match Atom::from_term(term) {
+        Ok(atom) if atoms::__const__().eq(&atom) => Ok(Mutability::Const),
+        Ok(atom) if atoms::var().eq(&atom) => Ok(Mutability::Var),
+        _ => Err(Error::Atom("global mutability must be :const or :var")),
+    }
+}
+
 fn term_to_arg_type(term: Term) -> Result<ValType, Error> {
     match Atom::from_term(term) {
         Ok(atom) => {
@@ -1,7 +1,16 @@
+use std::sync::Mutex;
+
+use rustler::{resource::ResourceArc, NifResult, Term};
 use wasmtime::Func;
 use wasmtime::Instance;
 
-use crate::environment::StoreOrCaller;
+use crate::environment::{StoreOrCaller, StoreOrCallerResource};
+use crate::instance::{spawn_call, CallTarget};
+
+/// A handle to a WebAssembly function, e.g. read from a table.
+pub struct FunctionResource {
+    pub inner: Mutex<Func>,
+}
 
 pub fn exists(instance: &Instance, store_or_caller: &mut StoreOrCaller, name: &str) -> bool {
     find(instance, store_or_caller, name).is_some()
@@ -10,3 +19,24 @@ pub fn exists(instance: &Instance, store_or_caller: &mut StoreOrCaller, name: &s
 pub fn find(instance: &Instance, store_or_caller: &mut StoreOrCaller, name: &str) -> Option<Func> {
     instance.get_func(store_or_caller, name)
 }
+
+// calls the function behind a function handle, just like `instance_call_exported_function`
+// calls an exported function.
+#[rustler::nif(name = "function_call")]
+pub fn call<'a>(
+    env: rustler::Env<'a>,
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    function_resource: ResourceArc<FunctionResource>,
+    params: Term,
+    timeout: Option<u64>,
+    from: Term,
+) -> NifResult<rustler::Atom> {
+    spawn_call(
+        env,
+        store_or_caller_resource,
+        CallTarget::Function(function_resource),
+        params,
+        timeout,
+        from,
+    )
+}
@@ -0,0 +1,141 @@
+//! Global API of an WebAssembly instance.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+use rustler::{resource::ResourceArc, Encoder, Error, NifResult, Term};
+use wasmtime::{Global, GlobalType, Instance, Mutability, Val};
+
+use crate::{
+    atoms,
+    environment::{StoreOrCaller, StoreOrCallerResource},
+    instance::{decode_function_param_terms, map_wasm_values_to_vals, InstanceResource},
+    module::global_info,
+};
+
+pub struct GlobalResource {
+    pub inner: Mutex<Global>,
+}
+
+#[derive(NifTuple)]
+pub struct GlobalResourceResponse {
+    ok: rustler::Atom,
+    resource: ResourceArc<GlobalResource>,
+}
+
+#[rustler::nif(name = "global_from_instance")]
+pub fn from_instance(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    instance_resource: ResourceArc<InstanceResource>,
+    global_name: String,
+) -> NifResult<GlobalResourceResponse> {
+    let instance: Instance = *(instance_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!(
+            "Could not unlock instance resource: {}",
+            e
+        )))
+    })?);
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    let global = instance
+        .get_global(store_or_caller, &global_name)
+        .ok_or_else(|| {
+            Error::Term(Box::new(format!(
+                "exported global `{}` not found",
+                global_name
+            )))
+        })?;
+    let resource = ResourceArc::new(GlobalResource {
+        inner: Mutex::new(global),
+    });
+    Ok(GlobalResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+#[rustler::nif(name = "global_get")]
+pub fn get<'a>(
+    env: rustler::Env<'a>,
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    global_resource: ResourceArc<GlobalResource>,
+) -> NifResult<Term<'a>> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not unlock store resource: {}", e)))
+        })?);
+    let global = global_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock global resource: {}", e)))
+    })?;
+    checked_global_type(store_or_caller, &global)?;
+    match global.get(store_or_caller) {
+        Val::I32(i) => Ok(i.encode(env)),
+        Val::I64(i) => Ok(i.encode(env)),
+        Val::F32(i) => Ok(f32::from_bits(i).encode(env)),
+        Val::F64(i) => Ok(f64::from_bits(i).encode(env)),
+        // encoding V128 is not yet supported by rustler
+        Val::V128(_) => Err(Error::Term(Box::new("unable_to_return_v128_type"))),
+        Val::FuncRef(_) => Err(Error::Term(Box::new("unable_to_return_func_ref_type"))),
+        Val::ExternRef(_) => Err(Error::Term(Box::new("unable_to_return_extern_ref_type"))),
+    }
+}
+
+#[rustler::nif(name = "global_set")]
+pub fn set(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    global_resource: ResourceArc<GlobalResource>,
+    value: Term,
+) -> NifResult<rustler::Atom> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not unlock store resource: {}", e)))
+        })?);
+    let global = global_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock global resource: {}", e)))
+    })?;
+    let ty = checked_global_type(store_or_caller, &global)?;
+    if let Mutability::Const = ty.mutability() {
+        return Err(Error::Term(Box::new(
+            "Could not set global: the global is immutable (:const)",
+        )));
+    }
+    let value = decode_function_param_terms(std::slice::from_ref(ty.content()), vec![value])
+        .map_err(|reason| Error::Term(Box::new(format!("Could not set global: {}", reason))))?;
+    global
+        .set(store_or_caller, map_wasm_values_to_vals(&value).remove(0))
+        .map_err(|err| Error::Term(Box::new(format!("Could not set global: {}", err))))?;
+    Ok(atoms::ok())
+}
+
+#[rustler::nif(name = "global_type")]
+pub fn global_type<'a>(
+    env: rustler::Env<'a>,
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    global_resource: ResourceArc<GlobalResource>,
+) -> NifResult<Term<'a>> {
+    let store_or_caller: &StoreOrCaller =
+        &*(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not unlock store resource: {}", e)))
+        })?);
+    let global = global_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock global resource: {}", e)))
+    })?;
+    Ok(global_info(
+        env,
+        &checked_global_type(store_or_caller, &global)?,
+    ))
+}
+
+// wasmtime panics when a global is used with a store it does not belong to
+fn checked_global_type(
+    store_or_caller: &StoreOrCaller,
+    global: &Global,
+) -> Result<GlobalType, Error> {
+    panic::catch_unwind(AssertUnwindSafe(|| global.ty(store_or_caller)))
+        .map_err(|_| Error::Term(Box::new("the global does not belong to this store")))
+}
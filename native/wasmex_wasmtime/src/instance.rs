@@ -4,20 +4,27 @@ use rustler::{
     resource::ResourceArc,
     types::tuple::make_tuple,
     types::ListIterator,
-    Encoder, Env as RustlerEnv, Error, MapIterator, NifResult, Term,
+    Atom, Encoder, Env as RustlerEnv, Error, LocalPid, MapIterator, NifResult, Term,
 };
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
 
-use wasmtime::{Instance, Linker, Module, Val, ValType};
+use wasmtime::{AsContextMut, Instance, Linker, Module, Trap, Val, ValType};
+use wiggle::anyhow;
 
 use crate::{
     atoms,
-    environment::{link_imports, CallbackTokenResource, StoreOrCaller, StoreOrCallerResource},
-    functions,
+    environment::{
+        link_imports, CallbackError, CallbackFailure, CallbackOptions, CallbackResult,
+        CallbackTokenResource, CallbackTrap, StoreOrCaller, StoreOrCallerResource,
+    },
+    functions::{self, FunctionResource},
     module::ModuleResource,
     printable_term_type::PrintableTermType,
-    store::StoreData,
+    store::{CallInterrupted, StoreData},
+    store_limits::ExceededLimit,
 };
 
 pub struct InstanceResource {
@@ -37,11 +44,17 @@ pub struct InstanceResourceResponse {
 // * module (ModuleResource): the compiled WASM module
 // * imports (map): a map defining eventual instance imports, may be empty if there are none.
 //   structure: %{namespace_name: %{import_name: {:fn, param_types, result_types, captured_function}}}
+//   function imports may name the pid receiving their callbacks as fifth tuple element
+// * callback_timeout (integer | nil): milliseconds imported functions wait for their callback
+// * callback_pid (pid | nil): the process receiving callbacks, defaults to the calling process
 #[rustler::nif(name = "instance_new")]
 pub fn new(
+    env: rustler::Env,
     store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
     module_resource: ResourceArc<ModuleResource>,
     imports: MapIterator,
+    callback_timeout: Option<u64>,
+    callback_pid: Option<LocalPid>,
 ) -> NifResult<InstanceResourceResponse> {
     let module = module_resource.inner.lock().map_err(|e| {
         rustler::Error::Term(Box::new(format!(
@@ -57,7 +70,11 @@ pub fn new(
             )))
         })?);
 
-    let instance = link_and_create_instance(store_or_caller, &module, imports)?;
+    let callback_options = CallbackOptions {
+        pid: callback_pid.unwrap_or_else(|| env.pid()),
+        timeout: callback_timeout.map(Duration::from_millis),
+    };
+    let instance = link_and_create_instance(store_or_caller, &module, imports, callback_options)?;
     let resource = ResourceArc::new(InstanceResource {
         inner: Mutex::new(instance),
     });
@@ -71,6 +88,7 @@ fn link_and_create_instance(
     store_or_caller: &mut StoreOrCaller,
     module: &Module,
     imports: MapIterator,
+    callback_options: CallbackOptions,
 ) -> Result<Instance, Error> {
     let mut linker = Linker::new(store_or_caller.engine());
     if let Some(_wasi_ctx) = &store_or_caller.data().wasi {
@@ -78,10 +96,27 @@ fn link_and_create_instance(
         wasmtime_wasi::add_to_linker(&mut linker, |s: &mut StoreData| s.wasi.as_mut().unwrap())
             .map_err(|err| Error::Term(Box::new(err.to_string())))?;
     }
-    link_imports(&mut linker, imports)?;
+    link_imports(
+        &mut linker,
+        store_or_caller,
+        module,
+        imports,
+        callback_options,
+    )?;
+    store_or_caller.as_context_mut().data_mut().limiter.reset();
     linker
-        .instantiate(store_or_caller, module)
-        .map_err(|err| Error::Term(Box::new(err.to_string())))
+        .instantiate(&mut *store_or_caller, module)
+        .map_err(|err| {
+            let exceeded_limit = store_or_caller
+                .data()
+                .limiter
+                .exceeded()
+                .or_else(|| ExceededLimit::from_count_error(&err));
+            match exceeded_limit {
+                Some(limit) => Error::Term(Box::new(limit.reason())),
+                None => Error::Term(Box::new(err.to_string())),
+            }
+        })
 }
 
 #[rustler::nif(name = "instance_function_export_exists")]
@@ -115,39 +150,153 @@ pub fn call_exported_function<'a>(
     instance_resource: ResourceArc<InstanceResource>,
     function_name: String,
     params: Term,
+    timeout: Option<u64>,
     from: Term,
-) -> rustler::Atom {
-    let pid = env.pid();
-    // create erlang environment for the thread
-    let mut thread_env = OwnedEnv::new();
-    // copy over params into the thread environment
-    let function_params = thread_env.save(params);
-    let from = thread_env.save(from);
+) -> NifResult<rustler::Atom> {
+    spawn_call(
+        env,
+        store_or_caller_resource,
+        CallTarget::Export(instance_resource, function_name),
+        params,
+        timeout,
+        from,
+    )
+}
+
+/// The function a call executes.
+pub(crate) enum CallTarget {
+    /// An exported function, looked up by name.
+    Export(ResourceArc<InstanceResource>, String),
+    /// A function handle, e.g. read from a table.
+    Function(ResourceArc<FunctionResource>),
+}
 
-    thread::spawn(move || {
+// Executes the call in a new OS thread. The result is sent to the calling process
+// as a `{:returned_function_call, result, from}` message.
+// Calls through the caller of an imported function are instead run on the wasm thread
+// waiting for the callback, once the store is paused.
+pub(crate) fn spawn_call(
+    env: rustler::Env,
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    target: CallTarget,
+    params: Term,
+    timeout: Option<u64>,
+    from: Term,
+) -> NifResult<rustler::Atom> {
+    // calls from within a callback run under the deadline of the outer call
+    if store_or_caller_resource.callback_token.is_some() && timeout.is_some() {
+        return Err(Error::Term(Box::new(
+            "Could not call function: calls through a caller can not have a timeout",
+        )));
+    }
+    // calls through a caller are nested in the outer call and can not be cancelled on their own
+    let call_id = match store_or_caller_resource.callback_token {
+        Some(_) => None,
+        None => {
+            let call_id = from.to_binary().as_slice().to_vec();
+            store_or_caller_resource
+                .interruption
+                .register_call(call_id.clone());
+            Some(call_id)
+        }
+    };
+    let call = PendingCall::new(
+        env.pid(),
+        store_or_caller_resource.clone(),
+        target,
+        params,
+        timeout.map(Duration::from_millis),
+        from,
+        call_id,
+    );
+    match &store_or_caller_resource.callback_token {
+        Some(callback_token) => callback_token.token.queue_nested_call(call)?,
+        None => {
+            thread::spawn(move || call.run());
+        }
+    };
+    Ok(atoms::ok())
+}
+
+/// A function call waiting to be executed, with its params copied into its own environment.
+pub(crate) struct PendingCall {
+    pid: LocalPid,
+    // erlang environment for the thread
+    thread_env: OwnedEnv,
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    target: CallTarget,
+    function_params: SavedTerm,
+    timeout: Option<Duration>,
+    from: SavedTerm,
+    call_id: Option<Vec<u8>>,
+}
+
+impl PendingCall {
+    fn new(
+        pid: LocalPid,
+        store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+        target: CallTarget,
+        params: Term,
+        timeout: Option<Duration>,
+        from: Term,
+        call_id: Option<Vec<u8>>,
+    ) -> Self {
+        let thread_env = OwnedEnv::new();
+        // copy over params into the thread environment
+        let function_params = thread_env.save(params);
+        let from = thread_env.save(from);
+        Self {
+            pid,
+            thread_env,
+            store_or_caller_resource,
+            target,
+            function_params,
+            timeout,
+            from,
+            call_id,
+        }
+    }
+
+    /// Executes the call and sends the result to the calling process.
+    pub(crate) fn run(self) {
+        let PendingCall {
+            pid,
+            mut thread_env,
+            store_or_caller_resource,
+            target,
+            function_params,
+            timeout,
+            from,
+            call_id,
+        } = self;
         thread_env.send_and_clear(&pid, |thread_env| {
-            execute_function(
+            let result = execute_function(
                 thread_env,
-                store_or_caller_resource,
-                instance_resource,
-                function_name,
+                &store_or_caller_resource,
+                target,
                 function_params,
+                timeout,
                 from,
-            )
-        })
-    });
-
-    atoms::ok()
+                call_id.as_deref(),
+            );
+            // before replying, so that cancelling the call once it returned has no effect
+            if let Some(call_id) = &call_id {
+                store_or_caller_resource.interruption.finish_call(call_id);
+            }
+            result
+        });
+    }
 }
 
-fn execute_function(
-    thread_env: RustlerEnv,
-    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
-    instance_resource: ResourceArc<InstanceResource>,
-    function_name: String,
+fn execute_function<'a>(
+    thread_env: RustlerEnv<'a>,
+    store_or_caller_resource: &StoreOrCallerResource,
+    target: CallTarget,
     function_params: SavedTerm,
+    timeout: Option<Duration>,
     from: SavedTerm,
-) -> Term {
+    call_id: Option<&[u8]>,
+) -> Term<'a> {
     let from = from
         .load(thread_env)
         .decode::<Term>()
@@ -156,19 +305,44 @@ fn execute_function(
         Ok(vec) => vec,
         Err(_) => return make_error_tuple(&thread_env, "could not load 'function params'", from),
     };
-    let instance: Instance = *(instance_resource.inner.lock().unwrap());
-    let mut store_or_caller = store_or_caller_resource.inner.lock().unwrap();
-    let function_result = functions::find(&instance, &mut store_or_caller, &function_name);
-    let function = match function_result {
-        Some(func) => func,
-        None => {
+    let mut store_or_caller = match store_or_caller_resource.inner.lock() {
+        Ok(store_or_caller) => store_or_caller,
+        Err(err) => {
             return make_error_tuple(
                 &thread_env,
-                &format!("exported function `{}` not found", function_name),
+                &format!("Could not unlock store_or_caller resource: {}", err),
                 from,
             )
         }
     };
+    let function = match target {
+        CallTarget::Export(instance_resource, function_name) => {
+            let instance: Instance = *(instance_resource.inner.lock().unwrap());
+            match functions::find(&instance, &mut store_or_caller, &function_name) {
+                Some(func) => func,
+                None => {
+                    return make_error_tuple(
+                        &thread_env,
+                        &format!("exported function `{}` not found", function_name),
+                        from,
+                    )
+                }
+            }
+        }
+        CallTarget::Function(function_resource) => {
+            let function = *(function_resource.inner.lock().unwrap());
+            // wasmtime panics when a function is used with a store it does not belong to
+            let ty = panic::catch_unwind(AssertUnwindSafe(|| function.ty(&*store_or_caller)));
+            if ty.is_err() {
+                return make_error_tuple(
+                    &thread_env,
+                    "the function does not belong to this store",
+                    from,
+                );
+            }
+            function
+        }
+    };
     let function_params_result = decode_function_param_terms(
         &function
             .ty(&*store_or_caller)
@@ -180,6 +354,16 @@ fn execute_function(
         Ok(vec) => map_wasm_values_to_vals(&vec),
         Err(reason) => return make_error_tuple(&thread_env, &reason, from),
     };
+    // calls from within a callback run under the deadline of the outer call
+    if let (StoreOrCaller::Store(store), Some(call_id)) = (&mut *store_or_caller, call_id) {
+        if let Err(reason) = store_or_caller_resource
+            .interruption
+            .start_call(call_id, timeout)
+        {
+            return make_error_tuple(&thread_env, &reason, from);
+        }
+        store.data_mut().limiter.set_in_call(true);
+    }
     let results_count = function.ty(&*store_or_caller).results().len();
     let mut results = vec![Val::null(); results_count];
     let call_result = function.call(
@@ -187,15 +371,12 @@ fn execute_function(
         function_params.as_slice(),
         &mut results,
     );
+    if let StoreOrCaller::Store(store) = &mut *store_or_caller {
+        store.data_mut().limiter.set_in_call(false);
+    }
     match call_result {
         Ok(_) => (),
-        Err(e) => {
-            return make_error_tuple(
-                &thread_env,
-                &format!("Error during function excecution: `{}`.", e),
-                from,
-            )
-        }
+        Err(e) => return make_call_error_tuple(&thread_env, &e, from),
     };
     let mut return_values: Vec<Term> = Vec::with_capacity(results_count);
     for value in results.iter().cloned() {
@@ -253,11 +434,7 @@ pub fn decode_function_param_terms(
     }
 
     let mut function_params = Vec::<WasmValue>::with_capacity(params.len());
-    for (nth, (param, given_param)) in params
-        .iter()
-        .zip(function_param_terms.into_iter())
-        .enumerate()
-    {
+    for (nth, (param, given_param)) in params.iter().zip(function_param_terms).enumerate() {
         let value = match (param, given_param.get_type()) {
             (ValType::I32, TermType::Number) => match given_param.decode::<i32>() {
                 Ok(value) => WasmValue::I32(value),
@@ -340,22 +517,71 @@ fn make_error_tuple<'a>(env: &RustlerEnv<'a>, reason: &str, from: Term<'a>) -> T
     )
 }
 
+// Traps we can recover from (e.g. running out of fuel or exceeding a store limit)
+// are reported as atoms, so that elixir can tell them apart from other execution errors.
+fn make_call_error_tuple<'a>(
+    env: &RustlerEnv<'a>,
+    error: &anyhow::Error,
+    from: Term<'a>,
+) -> Term<'a> {
+    let reason = match (
+        error.downcast_ref::<Trap>(),
+        error.downcast_ref::<CallInterrupted>(),
+        encode_callback_failure(env, error),
+    ) {
+        (Some(Trap::OutOfFuel), _, _) => atoms::out_of_fuel().encode(*env),
+        (_, Some(CallInterrupted::Timeout), _) => atoms::timeout().encode(*env),
+        (_, Some(CallInterrupted::Cancelled), _) => atoms::cancelled().encode(*env),
+        (_, _, Some(reason)) => reason,
+        _ => format!("Error during function excecution: `{}`.", error).encode(*env),
+    };
+    make_tuple(
+        *env,
+        &[
+            atoms::returned_function_call().encode(*env),
+            env.error_tuple(reason),
+            from,
+        ],
+    )
+}
+
+// Encodes why an imported function failed, if it failed because of its elixir callback.
+fn encode_callback_failure<'a>(env: &RustlerEnv<'a>, error: &anyhow::Error) -> Option<Term<'a>> {
+    if let Some(failure) = error.downcast_ref::<CallbackFailure>() {
+        return Some(match failure {
+            CallbackFailure::Timeout(_) => atoms::callback_timeout().encode(*env),
+            CallbackFailure::Abandoned => atoms::callback_abandoned().encode(*env),
+        });
+    }
+    if let Some(callback_error) = error.downcast_ref::<CallbackError>() {
+        return Some((atoms::callback_error(), callback_error).encode(*env));
+    }
+    match error.downcast_ref::<CallbackTrap>() {
+        Some(CallbackTrap::Trap(reason)) => Some((atoms::trap(), reason).encode(*env)),
+        Some(CallbackTrap::Exit(code)) => Some((atoms::exit(), code).encode(*env)),
+        None => None,
+    }
+}
+
 // called from elixir, params
 // * callback_token
-// * success: :ok | :error
+// * success: boolean
 //   indicates whether the call was successful or produced an elixir-error
-// * results: [number]
-//   return values of the elixir-callback - empty list when success-type is :error
+// * result: [number] | %{kind: atom, message: binary, stacktrace: binary | nil}
+//           | {:trap, binary} | {:exit, integer}
+//   return values of the elixir-callback, or when success is false, the error it produced
+//   or the trap it requested
 #[rustler::nif(name = "instance_receive_callback_result")]
 pub fn receive_callback_result(
     token_resource: ResourceArc<CallbackTokenResource>,
     success: bool,
-    result_list: ListIterator,
+    result: Term,
 ) -> NifResult<rustler::Atom> {
-    let results = if success {
+    let callback_result = if success {
         let return_types = token_resource.token.return_types.clone();
+        let result_list: ListIterator = result.decode()?;
         match decode_function_param_terms(&return_types, result_list.collect()) {
-            Ok(v) => v,
+            Ok(v) => CallbackResult::Returned(v),
             Err(reason) => {
                 return Err(Error::Term(Box::new(format!(
                     "could not convert callback result param to expected return signature: {}",
@@ -364,12 +590,24 @@ pub fn receive_callback_result(
             }
         }
     } else {
-        vec![]
+        decode_callback_failure(result)?
     };
 
     let mut result = token_resource.token.return_values.lock().unwrap();
-    *result = Some((success, results));
+    *result = Some(callback_result);
     token_resource.token.continue_signal.notify_one();
 
     Ok(atoms::ok())
 }
+
+fn decode_callback_failure(result: Term) -> NifResult<CallbackResult> {
+    match result.decode::<(Atom, Term)>() {
+        Ok((kind, reason)) if kind == atoms::trap() => Ok(CallbackResult::Trapped(
+            CallbackTrap::Trap(reason.decode()?),
+        )),
+        Ok((kind, code)) if kind == atoms::exit() => {
+            Ok(CallbackResult::Trapped(CallbackTrap::Exit(code.decode()?)))
+        }
+        _ => Ok(CallbackResult::Raised(result.decode()?)),
+    }
+}
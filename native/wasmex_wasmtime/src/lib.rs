@@ -1,13 +1,20 @@
+// lints triggered by code generated by the rustler macros
+#![allow(non_local_definitions, clippy::needless_borrows_for_generic_args)]
+
 pub mod atoms;
 pub mod caller;
+pub mod engine;
 pub mod environment;
 pub mod functions;
+pub mod global;
 pub mod instance;
 pub mod memory;
 pub mod module;
 pub mod pipe;
 pub mod printable_term_type;
 pub mod store;
+pub mod store_limits;
+pub mod table;
 
 #[macro_use]
 extern crate rustler;
@@ -17,40 +24,72 @@ use rustler::{Env, Term};
 rustler::init! {
     "Elixir.WasmexWasmtime.Native",
     [
+        engine::new,
+        engine::precompile_module,
+        functions::call,
+        global::from_instance,
+        global::get,
+        global::global_type,
+        global::set,
         instance::call_exported_function,
         instance::function_export_exists,
         instance::new,
         instance::receive_callback_result,
+        memory::export_names,
         memory::from_instance,
+        memory::from_instance_by_name,
         memory::get_byte,
         memory::grow,
         memory::length,
+        memory::new,
         memory::read_binary,
         memory::set_byte,
         memory::write_binary,
+        module::check_compatibility,
         module::compile,
+        module::compile_file,
+        module::compile_with_engine,
         module::exports,
         module::imports,
         module::name,
         module::serialize,
         module::unsafe_deserialize,
+        module::unsafe_deserialize_file,
+        module::validate,
         pipe::create,
         pipe::read_binary,
         pipe::seek,
         pipe::size,
         pipe::write_binary,
+        store::add_fuel,
+        store::cancel_call,
+        store::epoch_interruption,
+        store::engine,
+        store::fuel_consumed,
+        store::fuel_remaining,
         store::new,
         store::new_wasi,
+        table::from_instance,
+        table::get,
+        table::grow,
+        table::new,
+        table::set,
+        table::size,
+        table::table_type,
     ],
     load = on_load
 }
 
 fn on_load(env: Env, _info: Term) -> bool {
+    rustler::resource!(engine::EngineResource, env);
     rustler::resource!(environment::CallbackTokenResource, env);
     rustler::resource!(environment::StoreOrCallerResource, env);
+    rustler::resource!(functions::FunctionResource, env);
+    rustler::resource!(global::GlobalResource, env);
     rustler::resource!(instance::InstanceResource, env);
     rustler::resource!(memory::MemoryResource, env);
     rustler::resource!(module::ModuleResource, env);
     rustler::resource!(pipe::PipeResource, env);
+    rustler::resource!(table::TableResource, env);
     true
 }
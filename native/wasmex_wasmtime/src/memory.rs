@@ -6,7 +6,7 @@ use std::sync::Mutex;
 use rustler::resource::ResourceArc;
 use rustler::{Atom, Binary, Error, NewBinary, NifResult, Term};
 
-use wasmtime::{Instance, Memory, Store};
+use wasmtime::{Instance, Memory, MemoryType, Store};
 
 use crate::environment::{StoreOrCaller, StoreOrCallerResource};
 use crate::{atoms, instance};
@@ -21,6 +21,54 @@ pub struct MemoryResourceResponse {
     resource: ResourceArc<MemoryResource>,
 }
 
+#[derive(NifMap)]
+pub struct ExMemoryType {
+    pub(crate) minimum: u32,
+    pub(crate) maximum: Option<u32>,
+    pub(crate) shared: bool,
+}
+
+impl ExMemoryType {
+    pub(crate) fn to_wasmtime(&self) -> Result<MemoryType, Error> {
+        if !self.shared {
+            return Ok(MemoryType::new(self.minimum, self.maximum));
+        }
+        match self.maximum {
+            Some(maximum) => Ok(MemoryType::shared(self.minimum, maximum)),
+            None => Err(Error::Term(Box::new("shared memories must have a maximum"))),
+        }
+    }
+}
+
+#[rustler::nif(name = "memory_new")]
+pub fn new(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    memory_type: ExMemoryType,
+) -> NifResult<MemoryResourceResponse> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    if memory_type.shared {
+        return Err(Error::Term(Box::new(
+            "shared memories can only be created as imports",
+        )));
+    }
+    let memory = Memory::new(store_or_caller, memory_type.to_wasmtime()?)
+        .map_err(|err| Error::Term(Box::new(format!("Could not create memory: {}", err))))?;
+    let resource = ResourceArc::new(MemoryResource {
+        inner: Mutex::new(memory),
+    });
+
+    Ok(MemoryResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
 #[rustler::nif(name = "memory_from_instance")]
 pub fn from_instance(
     store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
@@ -50,6 +98,71 @@ pub fn from_instance(
     })
 }
 
+#[rustler::nif(name = "memory_from_instance_by_name")]
+pub fn from_instance_by_name(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    instance_resource: ResourceArc<instance::InstanceResource>,
+    memory_name: String,
+) -> rustler::NifResult<MemoryResourceResponse> {
+    let instance: Instance = *(instance_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!(
+            "Could not unlock instance resource: {}",
+            e
+        )))
+    })?);
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    let memory = instance
+        .get_memory(store_or_caller, &memory_name)
+        .ok_or_else(|| {
+            Error::Term(Box::new(format!(
+                "exported memory `{}` not found",
+                memory_name
+            )))
+        })?;
+    let resource = ResourceArc::new(MemoryResource {
+        inner: Mutex::new(memory),
+    });
+
+    Ok(MemoryResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+#[rustler::nif(name = "memory_export_names")]
+pub fn export_names(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    instance_resource: ResourceArc<instance::InstanceResource>,
+) -> rustler::NifResult<Vec<String>> {
+    let instance: Instance = *(instance_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!(
+            "Could not unlock instance resource: {}",
+            e
+        )))
+    })?);
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    let names = instance
+        .exports(store_or_caller)
+        .filter_map(|export| {
+            let name = export.name().to_string();
+            export.into_memory().map(|_| name)
+        })
+        .collect();
+    Ok(names)
+}
+
 #[rustler::nif(name = "memory_length")]
 pub fn length(
     store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
@@ -84,11 +197,17 @@ pub fn grow(
     })?;
     let store = match store_or_caller {
         StoreOrCaller::Store(store) => store,
-        StoreOrCaller::Caller(_) => {
+        StoreOrCaller::Caller(_) | StoreOrCaller::Expired => {
             return Err(Error::Term(Box::new("Cannot grow memory from caller")))
         }
     };
-    let old_pages = grow_by_pages(&memory, store, pages)?;
+    store.data_mut().limiter.reset();
+    let old_pages = grow_by_pages(&memory, store, pages).map_err(|err| {
+        match store.data().limiter.exceeded() {
+            Some(limit) => Error::Term(Box::new(limit.reason())),
+            None => err,
+        }
+    })?;
     Ok(old_pages)
 }
 
@@ -145,10 +264,15 @@ pub fn set_byte(
     Ok(atoms::ok())
 }
 
+// The default memory of an instance is the one exported as "memory", or else its first
+// exported memory. Callback contexts use the same rule.
 pub fn memory_from_instance(
     instance: &Instance,
     store_or_caller: &mut StoreOrCaller,
 ) -> Result<Memory, Error> {
+    if let Some(memory) = instance.get_memory(&mut *store_or_caller, "memory") {
+        return Ok(memory);
+    }
     instance
         .exports(store_or_caller)
         .find_map(|export| export.into_memory())
@@ -5,14 +5,13 @@ use rustler::{
 };
 use std::{collections::HashMap, sync::Mutex};
 
+use wasmparser::BinaryReaderError;
 use wasmtime::{
     Engine, ExternType, FuncType, GlobalType, MemoryType, Module, Mutability, TableType, ValType,
 };
+use wiggle::anyhow;
 
-use crate::{
-    atoms,
-    environment::{StoreOrCaller, StoreOrCallerResource},
-};
+use crate::{atoms, engine::EngineResource, environment::StoreOrCallerResource};
 
 pub struct ModuleResource {
     pub inner: Mutex<Module>,
@@ -24,23 +23,49 @@ pub struct ModuleResourceResponse {
     resource: ResourceArc<ModuleResource>,
 }
 
-#[rustler::nif(name = "module_compile")]
+#[rustler::nif(name = "module_compile", schedule = "DirtyCpu")]
 pub fn compile(
     store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
     binary: Binary,
 ) -> NifResult<ModuleResourceResponse> {
-    let store_or_caller: &mut StoreOrCaller =
-        &mut *(store_or_caller_resource.inner.lock().map_err(|e| {
-            rustler::Error::Term(Box::new(format!(
-                "Could not unlock store_or_caller resource as the mutex was poisoned: {}",
-                e
-            )))
-        })?);
+    // does not lock the store, so that compiling does not wait for a call running in it
+    compile_module(&store_or_caller_resource.engine.inner, binary)
+}
+
+#[rustler::nif(name = "module_compile_with_engine", schedule = "DirtyCpu")]
+pub fn compile_with_engine(
+    engine_resource: ResourceArc<EngineResource>,
+    binary: Binary,
+) -> NifResult<ModuleResourceResponse> {
+    let engine = &engine_resource.inner;
+    compile_module(engine, binary)
+}
+
+#[rustler::nif(name = "module_compile_file", schedule = "DirtyCpu")]
+pub fn compile_file(
+    engine_resource: ResourceArc<EngineResource>,
+    path: String,
+) -> NifResult<ModuleResourceResponse> {
+    let engine = &engine_resource.inner;
+    // reads the file directly, so its bytes never enter the BEAM heap
+    let module = Module::from_file(engine, path).map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not compile module: {:?}", e)))
+    })?;
+    let resource = ResourceArc::new(ModuleResource {
+        inner: Mutex::new(module),
+    });
+    Ok(ModuleResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+fn compile_module(engine: &Engine, binary: Binary) -> NifResult<ModuleResourceResponse> {
     let bytes = binary.as_slice();
     let bytes = wat::parse_bytes(bytes).map_err(|e| {
         rustler::Error::Term(Box::new(format!("Error while parsing bytes: {}.", e)))
     })?;
-    match Module::new(store_or_caller.engine(), bytes) {
+    match Module::new(engine, bytes) {
         Ok(module) => {
             let resource = ResourceArc::new(ModuleResource {
                 inner: Mutex::new(module),
@@ -57,6 +82,102 @@ pub fn compile(
     }
 }
 
+#[derive(NifMap)]
+pub struct ValidationError {
+    message: String,
+    offset: Option<usize>,
+    line: Option<usize>,
+    column: Option<usize>,
+    feature: Option<Atom>,
+}
+
+#[rustler::nif(name = "module_validate", schedule = "DirtyCpu")]
+pub fn validate(engine_resource: ResourceArc<EngineResource>, binary: Binary) -> NifResult<Atom> {
+    let engine = &engine_resource.inner;
+    let bytes = wat::parse_bytes(binary.as_slice())
+        .map_err(|e| rustler::Error::Term(Box::new(wat_validation_error(&e, binary.as_slice()))))?;
+    Module::validate(engine, &bytes)
+        .map_err(|e| rustler::Error::Term(Box::new(wasm_validation_error(&e))))?;
+    Ok(atoms::ok())
+}
+
+// wat errors expose their position only in their text, e.g.:
+// expected `(`
+//      --> <anon>:1:2
+fn wat_validation_error(error: &wat::Error, text: &[u8]) -> ValidationError {
+    let description = error.to_string();
+    let position = description
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("--> "))
+        .and_then(|location| {
+            let mut parts = location.rsplitn(3, ':');
+            let column = parts.next()?.parse::<usize>().ok()?;
+            let line = parts.next()?.parse::<usize>().ok()?;
+            Some((line, column))
+        });
+    // lines and columns are 1-based, columns count bytes
+    let offset = position.and_then(|(line, column)| {
+        let line_start: usize = text
+            .split(|byte| *byte == b'\n')
+            .take(line.saturating_sub(1))
+            .map(|text_line| text_line.len() + 1)
+            .sum();
+        Some(line_start + column.checked_sub(1)?)
+    });
+    ValidationError {
+        message: description.lines().next().unwrap_or_default().to_string(),
+        offset,
+        line: position.map(|(line, _column)| line),
+        column: position.map(|(_line, column)| column),
+        feature: None,
+    }
+}
+
+fn wasm_validation_error(error: &anyhow::Error) -> ValidationError {
+    match error.downcast_ref::<BinaryReaderError>() {
+        Some(error) => ValidationError {
+            message: error.message().to_string(),
+            offset: Some(error.offset()),
+            line: None,
+            column: None,
+            feature: missing_feature(error.message()),
+        },
+        None => ValidationError {
+            message: error.to_string(),
+            offset: None,
+            line: None,
+            column: None,
+            feature: None,
+        },
+    }
+}
+
+// The validator names disabled proposals only in its error messages,
+// e.g. "SIMD support is not enabled".
+fn missing_feature(message: &str) -> Option<Atom> {
+    let message = message.to_lowercase();
+    if !message.contains("enabled") {
+        return None;
+    }
+    if message.contains("simd") {
+        Some(atoms::simd())
+    } else if message.contains("reference types") {
+        Some(atoms::reference_types())
+    } else if message.contains("bulk memory") {
+        Some(atoms::bulk_memory())
+    } else if message.contains("multi-memory") || message.contains("multiple memories") {
+        Some(atoms::multi_memory())
+    } else if message.contains("multi-value") {
+        Some(atoms::multi_value())
+    } else if message.contains("memory64") {
+        Some(atoms::memory64())
+    } else if message.contains("threads") {
+        Some(atoms::threads())
+    } else {
+        None
+    }
+}
+
 #[rustler::nif(name = "module_name")]
 pub fn name(module_resource: ResourceArc<ModuleResource>) -> NifResult<String> {
     let module = module_resource.inner.lock().map_err(|e| {
@@ -158,7 +279,7 @@ fn val_type_to_atom(val_type: &ValType) -> Atom {
     }
 }
 
-fn global_info<'a>(env: rustler::Env<'a>, global_type: &GlobalType) -> Term<'a> {
+pub(crate) fn global_info<'a>(env: rustler::Env<'a>, global_type: &GlobalType) -> Term<'a> {
     let mut map = rustler::Term::map_new(env);
     match global_type.mutability() {
         Mutability::Const => {
@@ -183,7 +304,7 @@ fn global_info<'a>(env: rustler::Env<'a>, global_type: &GlobalType) -> Term<'a>
     make_tuple(env, &terms)
 }
 
-fn table_info<'a>(env: rustler::Env<'a>, table_type: &TableType) -> Term<'a> {
+pub(crate) fn table_info<'a>(env: rustler::Env<'a>, table_type: &TableType) -> Term<'a> {
     let mut map = rustler::Term::map_new(env);
     if let Some(i) = table_type.maximum() {
         map = map
@@ -259,16 +380,19 @@ pub fn serialize(
     Ok(binary.release(env))
 }
 
-#[rustler::nif(name = "module_unsafe_deserialize")]
-pub fn unsafe_deserialize(binary: Binary) -> NifResult<ModuleResourceResponse> {
-    let engine = Engine::default();
+#[rustler::nif(name = "module_unsafe_deserialize", schedule = "DirtyCpu")]
+pub fn unsafe_deserialize(
+    binary: Binary,
+    engine_resource: ResourceArc<EngineResource>,
+) -> NifResult<ModuleResourceResponse> {
+    let engine = &engine_resource.inner;
     // Safety: This function is inherently unsafe as the provided bytes:
     // 1. Are going to be deserialized directly into Rust objects.
     // 2. Contains the function assembly bodies and, if intercepted, a malicious actor could inject code into executable memory.
     // And as such, the deserialize method is unsafe.
     // However, there isn't much we can do about it here, we will warn users in elixir-land about this, though.
     let module = unsafe {
-        Module::deserialize(&engine, binary.as_slice()).map_err(|e| {
+        Module::deserialize(engine, binary.as_slice()).map_err(|e| {
             rustler::Error::Term(Box::new(format!("Could not deserialize module: {}", e)))
         })?
     };
@@ -280,3 +404,59 @@ pub fn unsafe_deserialize(binary: Binary) -> NifResult<ModuleResourceResponse> {
         resource,
     })
 }
+
+#[rustler::nif(name = "module_unsafe_deserialize_file", schedule = "DirtyCpu")]
+pub fn unsafe_deserialize_file(
+    path: String,
+    engine_resource: ResourceArc<EngineResource>,
+) -> NifResult<ModuleResourceResponse> {
+    let engine = &engine_resource.inner;
+    // Safety: see `unsafe_deserialize`. Additionally, the file is memory mapped and must not
+    // be changed while the module is alive.
+    let module = unsafe {
+        Module::deserialize_file(engine, path).map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not deserialize module: {}", e)))
+        })?
+    };
+    let resource = ResourceArc::new(ModuleResource {
+        inner: Mutex::new(module),
+    });
+    Ok(ModuleResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+#[rustler::nif(name = "module_check_compatibility", schedule = "DirtyCpu")]
+pub fn check_compatibility(
+    engine_resource: ResourceArc<EngineResource>,
+    binary: Binary,
+) -> NifResult<Atom> {
+    let engine = &engine_resource.inner;
+    // Safety: see `unsafe_deserialize`. Wasmtime checks compatibility while deserializing,
+    // the module is dropped right away.
+    match unsafe { Module::deserialize(engine, binary.as_slice()) } {
+        Ok(_module) => Ok(atoms::ok()),
+        Err(e) => Err(rustler::Error::Term(Box::new((
+            incompatibility_kind(binary.as_slice(), &e.to_string()),
+            e.to_string(),
+        )))),
+    }
+}
+
+// Wasmtime reports why a serialized module is incompatible only through the error message.
+// Serialized modules are ELF objects, everything else is not a serialized module at all.
+// Messages we do not recognize fall back to a generic kind.
+fn incompatibility_kind(binary: &[u8], message: &str) -> Atom {
+    if !binary.starts_with(b"\x7fELF") {
+        atoms::invalid()
+    } else if message.contains("Wasmtime version") {
+        atoms::version()
+    } else if message.starts_with("Module was compiled for") || message.contains("native host") {
+        atoms::target()
+    } else if message.starts_with("Module was compiled with") {
+        atoms::config()
+    } else {
+        atoms::incompatible()
+    }
+}
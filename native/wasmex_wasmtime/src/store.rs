@@ -1,13 +1,22 @@
-use rustler::{resource::ResourceArc, Error, NifResult};
-use std::{collections::HashMap, sync::Mutex};
+use rustler::{resource::ResourceArc, Error, NifResult, Term};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use wasi_common::WasiCtx;
-use wasmtime::{Config, Engine, Store};
+use wasmtime::{AsContext, AsContextMut, Store};
 use wasmtime_wasi::WasiCtxBuilder;
+use wiggle::anyhow;
 
 use crate::{
     atoms,
+    caller::StoreOrCallerLock,
+    engine::{EngineResource, EngineResourceResponse, EpochTicker},
     environment::{StoreOrCaller, StoreOrCallerResource, StoreOrCallerResourceResponse},
     pipe::{Pipe, PipeResource},
+    store_limits::{ExStoreLimits, Limiter},
 };
 
 #[derive(Debug, NifStruct)]
@@ -37,16 +46,136 @@ pub struct ExWasiOptions {
 
 pub struct StoreData {
     pub(crate) wasi: Option<WasiCtx>,
+    pub(crate) interruption: Arc<CallInterruption>,
+    pub(crate) limiter: Limiter,
+    // handed out as the engine of the store and its callers
+    pub(crate) engine: ResourceArc<EngineResource>,
+}
+
+/// The reason a running call was interrupted.
+#[derive(Debug, Clone, Copy)]
+pub enum CallInterrupted {
+    Timeout,
+    Cancelled,
+}
+
+impl fmt::Display for CallInterrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallInterrupted::Timeout => write!(f, "the call exceeded its timeout"),
+            CallInterrupted::Cancelled => write!(f, "the call was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for CallInterrupted {}
+
+// Decides whether the call running in a store must be interrupted.
+// It is checked on every epoch tick and lives outside of the store mutex,
+// so that a running call (which holds that mutex) can be cancelled.
+// Calls are identified by their `from` term (encoded with `term_to_binary`), so that a
+// cancellation only ever applies to the call it was meant for.
+pub struct CallInterruption {
+    calls: Mutex<Calls>,
+    epoch_ticker: Option<Arc<EpochTicker>>,
+}
+
+#[derive(Default)]
+struct Calls {
+    // calls which were started but did not finish yet, and whether they were cancelled
+    pending: HashMap<Vec<u8>, bool>,
+    running: Option<Vec<u8>>,
+    deadline: Option<Instant>,
+}
+
+impl CallInterruption {
+    fn new(epoch_ticker: Option<Arc<EpochTicker>>) -> Self {
+        Self {
+            calls: Mutex::new(Calls::default()),
+            epoch_ticker,
+        }
+    }
+
+    pub(crate) fn epoch_interruption(&self) -> bool {
+        self.epoch_ticker.is_some()
+    }
+
+    // Registers a call as soon as it is spawned, so that it can be cancelled before it runs.
+    pub(crate) fn register_call(&self, call_id: Vec<u8>) {
+        self.calls.lock().unwrap().pending.insert(call_id, false);
+    }
+
+    pub(crate) fn start_call(
+        &self,
+        call_id: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<(), String> {
+        if timeout.is_some() && self.epoch_ticker.is_none() {
+            return Err(
+                "call timeouts require `epoch_interruption: true` in the engine config".into(),
+            );
+        }
+        let mut calls = self.calls.lock().unwrap();
+        calls.running = Some(call_id.to_vec());
+        calls.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        Ok(())
+    }
+
+    // The next call may already be running once this call released the store.
+    pub(crate) fn finish_call(&self, call_id: &[u8]) {
+        let mut calls = self.calls.lock().unwrap();
+        calls.pending.remove(call_id);
+        if calls.running.as_deref() == Some(call_id) {
+            calls.running = None;
+            calls.deadline = None;
+        }
+    }
+
+    // Cancels the given call, or the running call if none is given.
+    // Calls which already finished are not affected.
+    pub(crate) fn cancel(&self, call_id: Option<&[u8]>) -> Result<(), String> {
+        if self.epoch_ticker.is_none() {
+            return Err(
+                "cancelling calls requires `epoch_interruption: true` in the engine config".into(),
+            );
+        }
+        let mut calls = self.calls.lock().unwrap();
+        let call_id = match call_id {
+            Some(call_id) => call_id.to_vec(),
+            None => match calls.running.clone() {
+                Some(running) => running,
+                None => return Ok(()),
+            },
+        };
+        if let Some(cancelled) = calls.pending.get_mut(&call_id) {
+            *cancelled = true;
+        }
+        Ok(())
+    }
+
+    // Returns the number of epoch ticks the call may continue, or the reason to stop it.
+    fn check(&self) -> Result<u64, anyhow::Error> {
+        let calls = self.calls.lock().unwrap();
+        let cancelled = match &calls.running {
+            Some(running) => calls.pending.get(running).copied().unwrap_or(false),
+            None => false,
+        };
+        if cancelled {
+            return Err(CallInterrupted::Cancelled.into());
+        }
+        match calls.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(CallInterrupted::Timeout.into()),
+            _ => Ok(1),
+        }
+    }
 }
 
 #[rustler::nif(name = "store_new")]
-pub fn new() -> NifResult<StoreOrCallerResourceResponse> {
-    let config = Config::new();
-    let engine = Engine::new(&config).map_err(|err| Error::Term(Box::new(err.to_string())))?;
-    let store = Store::new(&engine, StoreData { wasi: None });
-    let resource = ResourceArc::new(StoreOrCallerResource {
-        inner: Mutex::new(StoreOrCaller::Store(store)),
-    });
+pub fn new(
+    limits: Option<ExStoreLimits>,
+    engine_resource: ResourceArc<EngineResource>,
+) -> NifResult<StoreOrCallerResourceResponse> {
+    let resource = new_store_resource(engine_resource, None, limits)?;
     Ok(StoreOrCallerResourceResponse {
         ok: atoms::ok(),
         resource,
@@ -54,7 +183,11 @@ pub fn new() -> NifResult<StoreOrCallerResourceResponse> {
 }
 
 #[rustler::nif(name = "store_new_wasi")]
-pub fn new_wasi(options: ExWasiOptions) -> NifResult<StoreOrCallerResourceResponse> {
+pub fn new_wasi(
+    options: ExWasiOptions,
+    limits: Option<ExStoreLimits>,
+    engine_resource: ResourceArc<EngineResource>,
+) -> NifResult<StoreOrCallerResourceResponse> {
     let wasi_env = &options
         .env
         .iter()
@@ -77,23 +210,127 @@ pub fn new_wasi(options: ExWasiOptions) -> NifResult<StoreOrCallerResourceRespon
     let builder = wasi_preopen_directories(options.preopen, builder)?;
     let wasi_ctx = builder.build();
 
-    let config = Config::new();
-    let engine = Engine::new(&config).map_err(|err| Error::Term(Box::new(err.to_string())))?;
-    let store = Store::new(
-        &engine,
+    let resource = new_store_resource(engine_resource, Some(wasi_ctx), limits)?;
+    Ok(StoreOrCallerResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+fn new_store_resource(
+    engine_resource: ResourceArc<EngineResource>,
+    wasi: Option<WasiCtx>,
+    limits: Option<ExStoreLimits>,
+) -> NifResult<ResourceArc<StoreOrCallerResource>> {
+    let interruption = Arc::new(CallInterruption::new(engine_resource.epoch_ticker.clone()));
+    let mut store = Store::new(
+        &engine_resource.inner,
         StoreData {
-            wasi: Some(wasi_ctx),
+            wasi,
+            interruption: interruption.clone(),
+            limiter: Limiter::new(limits),
+            engine: engine_resource.clone(),
         },
     );
-    let resource = ResourceArc::new(StoreOrCallerResource {
-        inner: Mutex::new(StoreOrCaller::Store(store)),
-    });
-    Ok(StoreOrCallerResourceResponse {
+    store.limiter(|data| &mut data.limiter);
+    // Only relevant if epoch interruption is enabled: instead of trapping once the
+    // epoch deadline is reached, we check whether the running call must be interrupted.
+    let callback_interruption = interruption.clone();
+    store.epoch_deadline_callback(move |_| callback_interruption.check());
+    store.set_epoch_deadline(1);
+    Ok(ResourceArc::new(StoreOrCallerResource {
+        inner: StoreOrCallerLock::new(StoreOrCaller::Store(store)),
+        interruption,
+        engine: engine_resource,
+        callback_token: None,
+    }))
+}
+
+#[rustler::nif(name = "store_or_caller_add_fuel")]
+pub fn add_fuel(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    fuel: u64,
+) -> NifResult<rustler::Atom> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    store_or_caller
+        .as_context_mut()
+        .add_fuel(fuel)
+        .map_err(|err| Error::Term(Box::new(err.to_string())))?;
+    Ok(atoms::ok())
+}
+
+#[rustler::nif(name = "store_or_caller_fuel_remaining")]
+pub fn fuel_remaining(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+) -> NifResult<u64> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    // consuming no fuel is the only way to read the remaining fuel
+    store_or_caller
+        .as_context_mut()
+        .consume_fuel(0)
+        .map_err(|err| Error::Term(Box::new(err.to_string())))
+}
+
+#[rustler::nif(name = "store_or_caller_fuel_consumed")]
+pub fn fuel_consumed(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+) -> NifResult<u64> {
+    let store_or_caller: &StoreOrCaller =
+        &*(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    store_or_caller
+        .as_context()
+        .fuel_consumed()
+        .ok_or_else(|| Error::Term(Box::new("fuel is not enabled in this store's engine")))
+}
+
+#[rustler::nif(name = "store_or_caller_engine")]
+pub fn engine(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+) -> NifResult<EngineResourceResponse> {
+    // does not lock the store, so that the engine of a store running a call can be read
+    Ok(EngineResourceResponse {
         ok: atoms::ok(),
-        resource,
+        resource: store_or_caller_resource.engine.clone(),
     })
 }
 
+#[rustler::nif(name = "store_or_caller_epoch_interruption")]
+pub fn epoch_interruption(store_or_caller_resource: ResourceArc<StoreOrCallerResource>) -> bool {
+    // does not lock the store, so that it can be asked while a call is running
+    store_or_caller_resource.interruption.epoch_interruption()
+}
+
+#[rustler::nif(name = "store_or_caller_cancel_call")]
+pub fn cancel_call(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    from: Option<Term>,
+) -> NifResult<rustler::Atom> {
+    // does not lock the store, which is held by the running call
+    let call_id = from.map(|from| from.to_binary().as_slice().to_vec());
+    store_or_caller_resource
+        .interruption
+        .cancel(call_id.as_deref())
+        .map_err(|reason| Error::Term(Box::new(reason)))?;
+    Ok(atoms::ok())
+}
+
 fn add_pipe(
     pipe: Option<ExPipe>,
     builder: WasiCtxBuilder,
@@ -115,17 +352,13 @@ fn wasi_preopen_directories(
     preopens: Vec<ExWasiPreopenOptions>,
     builder: WasiCtxBuilder,
 ) -> Result<WasiCtxBuilder, rustler::Error> {
-    let builder = preopens.iter().fold(Ok(builder), |builder, preopen| {
-        preopen_directory(builder, preopen)
-    })?;
-    Ok(builder)
+    preopens.iter().try_fold(builder, preopen_directory)
 }
 
 fn preopen_directory(
-    builder: Result<WasiCtxBuilder, Error>,
+    builder: WasiCtxBuilder,
     preopen: &ExWasiPreopenOptions,
 ) -> Result<WasiCtxBuilder, Error> {
-    let builder = builder?;
     let path = &preopen.path;
     let dir = wasmtime_wasi::Dir::from_std_file(
         std::fs::File::open(path).map_err(|err| rustler::Error::Term(Box::new(err.to_string())))?,
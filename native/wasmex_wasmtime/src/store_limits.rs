@@ -0,0 +1,221 @@
+//! Resource limits of a store.
+//! Limits how much memory, how many table elements, instances, tables, and memories
+//! WebAssembly running in a store may use.
+
+use std::{sync::Arc, time::Duration};
+
+use rustler::{resource::ResourceArc, Atom, Encoder, LocalPid, OwnedEnv};
+use wasmtime::{ResourceLimiter, StoreLimits, StoreLimitsBuilder, ValType};
+use wiggle::anyhow;
+
+use crate::{
+    atoms,
+    environment::{CallbackResult, CallbackToken, CallbackTokenResource},
+    instance::WasmValue,
+};
+
+#[derive(NifStruct)]
+#[module = "WasmexWasmtime.StoreLimits"]
+pub struct ExStoreLimits {
+    memory_size: Option<usize>,
+    table_elements: Option<u32>,
+    instances: Option<usize>,
+    tables: Option<usize>,
+    memories: Option<usize>,
+    growth_handler: Option<LocalPid>,
+    growth_timeout: Option<u64>,
+}
+
+impl ExStoreLimits {
+    fn to_wasmtime(&self) -> StoreLimits {
+        let mut builder = StoreLimitsBuilder::new();
+        if let Some(memory_size) = self.memory_size {
+            builder = builder.memory_size(memory_size);
+        }
+        if let Some(table_elements) = self.table_elements {
+            builder = builder.table_elements(table_elements);
+        }
+        if let Some(instances) = self.instances {
+            builder = builder.instances(instances);
+        }
+        if let Some(tables) = self.tables {
+            builder = builder.tables(tables);
+        }
+        if let Some(memories) = self.memories {
+            builder = builder.memories(memories);
+        }
+        builder.build()
+    }
+}
+
+/// The kind of resource a denied request asked for.
+#[derive(Debug, Clone, Copy)]
+pub enum ExceededLimit {
+    Memory,
+    Table,
+    Instances,
+    Tables,
+    Memories,
+}
+
+impl ExceededLimit {
+    pub(crate) fn reason(&self) -> (Atom, Atom) {
+        let kind = match self {
+            ExceededLimit::Memory => atoms::memory(),
+            ExceededLimit::Table => atoms::table(),
+            ExceededLimit::Instances => atoms::instances(),
+            ExceededLimit::Tables => atoms::tables(),
+            ExceededLimit::Memories => atoms::memories(),
+        };
+        (atoms::resource_limit_exceeded(), kind)
+    }
+
+    // Wasmtime reports exceeded count limits only through the error message.
+    pub(crate) fn from_count_error(error: &anyhow::Error) -> Option<Self> {
+        let message = error.to_string();
+        if message.starts_with("resource limit exceeded: instance count") {
+            Some(ExceededLimit::Instances)
+        } else if message.starts_with("resource limit exceeded: table count") {
+            Some(ExceededLimit::Tables)
+        } else if message.starts_with("resource limit exceeded: memory count") {
+            Some(ExceededLimit::Memories)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(NifMap)]
+struct GrowthRequest {
+    current: u64,
+    desired: u64,
+    maximum: Option<u64>,
+}
+
+// Enforces the limits of a store. Wasmtime only reports that growth failed
+// (e.g. `Memory::grow` errors), so we remember the denial to be able to tell
+// an exceeded limit apart from other errors. Growth denied within a call is not
+// an error: WebAssembly sees a failed `memory.grow` and continues, so later traps
+// are not reported as exceeded limits.
+//
+// Growth within the static limits may additionally be decided by an elixir process
+// (the growth handler), which is asked for every growth requested by a running call.
+// It has to answer within the growth timeout of the store limits.
+pub struct Limiter {
+    limits: StoreLimits,
+    growth_handler: Option<LocalPid>,
+    growth_timeout: Option<Duration>,
+    in_call: bool,
+    exceeded: Option<ExceededLimit>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: Option<ExStoreLimits>) -> Self {
+        let (limits, growth_handler, growth_timeout) = match limits {
+            Some(limits) => (
+                limits.to_wasmtime(),
+                limits.growth_handler,
+                limits.growth_timeout.map(Duration::from_millis),
+            ),
+            None => (StoreLimits::default(), None, None),
+        };
+        Self {
+            limits,
+            growth_handler,
+            growth_timeout,
+            in_call: false,
+            exceeded: None,
+        }
+    }
+
+    pub(crate) fn exceeded(&self) -> Option<ExceededLimit> {
+        self.exceeded
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.exceeded = None;
+    }
+
+    // The growth handler is only asked from the OS thread running a call.
+    // Other growth (e.g. during instantiation) happens on the thread of the calling
+    // elixir process, which might be the growth handler itself.
+    pub(crate) fn set_in_call(&mut self, in_call: bool) {
+        self.in_call = in_call;
+    }
+
+    fn ask_growth_handler(&self, kind: Atom, request: GrowthRequest) -> bool {
+        let pid = match (self.growth_handler, self.in_call) {
+            (Some(pid), true) => pid,
+            _ => return true,
+        };
+        let token = Arc::new(CallbackToken::new(vec![ValType::I32]));
+        let callback_token = ResourceArc::new(CallbackTokenResource {
+            token: token.clone(),
+        });
+
+        let mut msg_env = OwnedEnv::new();
+        msg_env.send_and_clear(&pid, |env| {
+            (kind, request, callback_token.clone()).encode(env)
+        });
+        drop(callback_token);
+
+        // `receive_callback_result` stores the answer and signals the condvar.
+        // Growth is denied if the growth handler exits or times out without answering.
+        let allowed = match token.wait_for_result(self.growth_timeout) {
+            Ok(result) => match result.as_ref() {
+                Some(CallbackResult::Returned(values)) => {
+                    matches!(values.as_slice(), [WasmValue::I32(1)])
+                }
+                _ => false,
+            },
+            Err(_) => false,
+        };
+        allowed
+    }
+}
+
+impl ResourceLimiter for Limiter {
+    fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> bool {
+        let allowed = self.limits.memory_growing(current, desired, maximum)
+            && self.ask_growth_handler(
+                atoms::memory_growing(),
+                GrowthRequest {
+                    current: current as u64,
+                    desired: desired as u64,
+                    maximum: maximum.map(|maximum| maximum as u64),
+                },
+            );
+        if !allowed {
+            self.exceeded = Some(ExceededLimit::Memory);
+        }
+        allowed
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        let allowed = self.limits.table_growing(current, desired, maximum)
+            && self.ask_growth_handler(
+                atoms::table_growing(),
+                GrowthRequest {
+                    current: current as u64,
+                    desired: desired as u64,
+                    maximum: maximum.map(|maximum| maximum as u64),
+                },
+            );
+        if !allowed {
+            self.exceeded = Some(ExceededLimit::Table);
+        }
+        allowed
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
+    }
+}
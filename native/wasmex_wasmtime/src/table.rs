@@ -0,0 +1,316 @@
+//! Table API of WebAssembly.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+use rustler::{
+    env::{OwnedEnv, SavedTerm},
+    resource::ResourceArc,
+    Encoder, Env, Error, NifResult, Term,
+};
+use wasmtime::{AsContextMut, ExternRef, Instance, Table, TableType, Val, ValType};
+
+use crate::{
+    atoms,
+    environment::{StoreOrCaller, StoreOrCallerResource},
+    functions::FunctionResource,
+    instance::InstanceResource,
+    module::table_info,
+};
+
+pub struct TableResource {
+    pub inner: Mutex<Table>,
+}
+
+#[derive(NifTuple)]
+pub struct TableResourceResponse {
+    ok: rustler::Atom,
+    resource: ResourceArc<TableResource>,
+}
+
+#[rustler::nif(name = "table_new")]
+pub fn new(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    ty: Term,
+) -> NifResult<TableResourceResponse> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    let table = new_table(store_or_caller, decode_table_type(ty)?)?;
+    let resource = ResourceArc::new(TableResource {
+        inner: Mutex::new(table),
+    });
+    Ok(TableResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+#[rustler::nif(name = "table_from_instance")]
+pub fn from_instance(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    instance_resource: ResourceArc<InstanceResource>,
+    table_name: String,
+) -> NifResult<TableResourceResponse> {
+    let instance: Instance = *(instance_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!(
+            "Could not unlock instance resource: {}",
+            e
+        )))
+    })?);
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    let table = instance
+        .get_table(store_or_caller, &table_name)
+        .ok_or_else(|| {
+            Error::Term(Box::new(format!(
+                "exported table `{}` not found",
+                table_name
+            )))
+        })?;
+    let resource = ResourceArc::new(TableResource {
+        inner: Mutex::new(table),
+    });
+    Ok(TableResourceResponse {
+        ok: atoms::ok(),
+        resource,
+    })
+}
+
+#[rustler::nif(name = "table_size")]
+pub fn size(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    table_resource: ResourceArc<TableResource>,
+) -> NifResult<u32> {
+    let store_or_caller: &StoreOrCaller =
+        &*(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not unlock store resource: {}", e)))
+        })?);
+    let table = table_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock table resource: {}", e)))
+    })?;
+    checked_table_type(store_or_caller, &table)?;
+    Ok(table.size(store_or_caller))
+}
+
+#[rustler::nif(name = "table_type")]
+pub fn table_type<'a>(
+    env: rustler::Env<'a>,
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    table_resource: ResourceArc<TableResource>,
+) -> NifResult<Term<'a>> {
+    let store_or_caller: &StoreOrCaller =
+        &*(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not unlock store resource: {}", e)))
+        })?);
+    let table = table_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock table resource: {}", e)))
+    })?;
+    Ok(table_info(
+        env,
+        &checked_table_type(store_or_caller, &table)?,
+    ))
+}
+
+#[rustler::nif(name = "table_grow")]
+pub fn grow(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    table_resource: ResourceArc<TableResource>,
+    delta: u32,
+    init: Term,
+) -> NifResult<u32> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!(
+                "Could not unlock store_or_caller resource: {}",
+                e
+            )))
+        })?);
+    let table = table_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock table resource: {}", e)))
+    })?;
+    let init = decode_element(
+        init,
+        &checked_table_type(store_or_caller, &table)?.element(),
+    )?;
+    store_or_caller.as_context_mut().data_mut().limiter.reset();
+    table
+        .grow(&mut *store_or_caller, delta, init)
+        .map_err(|err| match store_or_caller.data().limiter.exceeded() {
+            Some(limit) => Error::Term(Box::new(limit.reason())),
+            None => Error::Term(Box::new(format!("Failed to grow the table: {}.", err))),
+        })
+}
+
+#[rustler::nif(name = "table_get")]
+pub fn get<'a>(
+    env: Env<'a>,
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    table_resource: ResourceArc<TableResource>,
+    index: u32,
+) -> NifResult<Term<'a>> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not unlock store resource: {}", e)))
+        })?);
+    let table = table_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock table resource: {}", e)))
+    })?;
+    checked_table_type(store_or_caller, &table)?;
+    let element = table
+        .get(store_or_caller, index)
+        .ok_or_else(|| Error::Term(Box::new(format!("table index {} out of bounds", index))))?;
+    encode_element(env, element)
+}
+
+#[rustler::nif(name = "table_set")]
+pub fn set(
+    store_or_caller_resource: ResourceArc<StoreOrCallerResource>,
+    table_resource: ResourceArc<TableResource>,
+    index: u32,
+    value: Term,
+) -> NifResult<rustler::Atom> {
+    let store_or_caller: &mut StoreOrCaller =
+        &mut *(store_or_caller_resource.inner.try_lock().map_err(|e| {
+            rustler::Error::Term(Box::new(format!("Could not unlock store resource: {}", e)))
+        })?);
+    let table = table_resource.inner.lock().map_err(|e| {
+        rustler::Error::Term(Box::new(format!("Could not unlock table resource: {}", e)))
+    })?;
+    let value = decode_element(
+        value,
+        &checked_table_type(store_or_caller, &table)?.element(),
+    )?;
+    table
+        .set(store_or_caller, index, value)
+        .map_err(|err| Error::Term(Box::new(format!("Could not set table element: {}", err))))?;
+    Ok(atoms::ok())
+}
+
+// wasmtime panics when a table is used with a store it does not belong to
+fn checked_table_type(store_or_caller: &StoreOrCaller, table: &Table) -> Result<TableType, Error> {
+    panic::catch_unwind(AssertUnwindSafe(|| table.ty(store_or_caller)))
+        .map_err(|_| Error::Term(Box::new("the table does not belong to this store")))
+}
+
+/// An elixir term wrapped in an extern ref.
+pub(crate) struct ExternTerm {
+    inner: Mutex<(OwnedEnv, SavedTerm)>,
+}
+
+impl ExternTerm {
+    fn new(term: Term) -> Self {
+        let owned_env = OwnedEnv::new();
+        let saved_term = owned_env.save(term);
+        Self {
+            inner: Mutex::new((owned_env, saved_term)),
+        }
+    }
+
+    fn load<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let guard = self.inner.lock().unwrap();
+        let (owned_env, saved_term) = &*guard;
+        owned_env.run(|owned_env| saved_term.load(owned_env).in_env(env))
+    }
+}
+
+// Table elements are returned as `{:func_ref, function_resource | nil}` or
+// `{:extern_ref, term | nil}`, so that elixir can wrap function handles.
+fn encode_element<'a>(env: Env<'a>, element: Val) -> NifResult<Term<'a>> {
+    match element {
+        Val::FuncRef(None) => Ok((atoms::func_ref(), atoms::__nil__()).encode(env)),
+        Val::FuncRef(Some(func)) => {
+            let resource = ResourceArc::new(FunctionResource {
+                inner: Mutex::new(func),
+            });
+            Ok((atoms::func_ref(), resource).encode(env))
+        }
+        Val::ExternRef(None) => Ok((atoms::extern_ref(), atoms::__nil__()).encode(env)),
+        Val::ExternRef(Some(extern_ref)) => match extern_ref.data().downcast_ref::<ExternTerm>() {
+            Some(extern_term) => Ok((atoms::extern_ref(), extern_term.load(env)).encode(env)),
+            None => Err(Error::Term(Box::new(
+                "extern ref does not wrap an elixir term",
+            ))),
+        },
+        _ => Err(Error::Term(Box::new("unsupported table element"))),
+    }
+}
+
+// `nil` is the null reference. Func-ref elements are given as `WasmexWasmtime.Function`
+// structs, extern-ref elements wrap any other term.
+fn decode_element(term: Term, element_type: &ValType) -> Result<Val, Error> {
+    let is_nil = matches!(term.decode::<rustler::Atom>(), Ok(atom) if atoms::__nil__() == atom);
+    match (element_type, is_nil) {
+        (ValType::FuncRef, true) => Ok(Val::FuncRef(None)),
+        (ValType::FuncRef, false) => {
+            let function_resource: ResourceArc<FunctionResource> = term
+                .map_get(atoms::resource().to_term(term.get_env()))
+                .and_then(|resource| resource.decode())
+                .map_err(|_| {
+                    Error::Term(Box::new(
+                        "func_ref table elements must be a WasmexWasmtime.Function or nil",
+                    ))
+                })?;
+            let func = *(function_resource.inner.lock().map_err(|e| {
+                rustler::Error::Term(Box::new(format!(
+                    "Could not unlock function resource: {}",
+                    e
+                )))
+            })?);
+            Ok(Val::FuncRef(Some(func)))
+        }
+        (ValType::ExternRef, true) => Ok(Val::ExternRef(None)),
+        (ValType::ExternRef, false) => {
+            let extern_ref = ExternRef::new(ExternTerm::new(term));
+            Ok(Val::ExternRef(Some(extern_ref)))
+        }
+        _ => Err(Error::Term(Box::new("unsupported table element type"))),
+    }
+}
+
+/// Creates a table with all elements set to null.
+pub(crate) fn new_table(
+    store_or_caller: &mut StoreOrCaller,
+    ty: TableType,
+) -> Result<Table, Error> {
+    let init = match ty.element() {
+        ValType::ExternRef => Val::ExternRef(None),
+        _ => Val::FuncRef(None),
+    };
+    Table::new(store_or_caller, ty, init)
+        .map_err(|err| Error::Term(Box::new(format!("Could not create table: {}", err))))
+}
+
+/// Decodes a table type like `%{minimum: 1, maximum: 2, type: :func_ref}`.
+/// The shape matches the table info reported for module imports and exports.
+pub(crate) fn decode_table_type(term: Term) -> Result<TableType, Error> {
+    let minimum: u32 = term
+        .map_get(atoms::minimum().to_term(term.get_env()))?
+        .decode()?;
+    let maximum: Option<u32> = match term.map_get(atoms::maximum().to_term(term.get_env())) {
+        Ok(maximum) => maximum.decode()?,
+        Err(_) => None,
+    };
+    let element_type = term
+        .map_get(atoms::__type__().to_term(term.get_env()))?
+        .decode::<rustler::Atom>()?;
+    let element_type = if atoms::func_ref().eq(&element_type) {
+        ValType::FuncRef
+    } else if atoms::extern_ref().eq(&element_type) {
+        ValType::ExternRef
+    } else {
+        return Err(Error::Term(Box::new(
+            "table type must be :func_ref or :extern_ref",
+        )));
+    };
+    Ok(TableType::new(element_type, minimum, maximum))
+}
@@ -0,0 +1,109 @@
+defmodule WasmexWasmtime.EngineTest do
+  use ExUnit.Case, async: true
+  doctest WasmexWasmtime.Engine
+
+  alias WasmexWasmtime.{Engine, EngineConfig}
+
+  @simd_wat """
+  (module
+    (func (export "zero") (result v128)
+      v128.const i32x4 0 0 0 0))
+  """
+
+  @multi_memory_wat "(module (memory 1) (memory 1))"
+
+  describe "new/1" do
+    test "creates an engine with the default config" do
+      {:ok, engine} = Engine.new()
+      assert {:ok, _module} = WasmexWasmtime.Module.compile(engine, @simd_wat)
+    end
+
+    test "disabling a wasm proposal rejects modules using it" do
+      {:ok, engine} = Engine.new(%EngineConfig{wasm_simd: false})
+      assert {:error, reason} = WasmexWasmtime.Module.compile(engine, @simd_wat)
+      assert reason =~ "SIMD support is not enabled"
+    end
+
+    test "enabling a wasm proposal accepts modules using it" do
+      assert {:error, _reason} =
+               WasmexWasmtime.Module.compile(Engine.new_default(), @multi_memory_wat)
+
+      {:ok, engine} = Engine.new(%EngineConfig{wasm_multi_memory: true})
+      assert {:ok, _module} = WasmexWasmtime.Module.compile(engine, @multi_memory_wat)
+    end
+
+    test "compiles modules with every cranelift optimization level" do
+      for opt_level <- [:none, :speed, :speed_and_size] do
+        {:ok, engine} = Engine.new(%EngineConfig{cranelift_opt_level: opt_level})
+        assert {:ok, _module} = WasmexWasmtime.Module.compile(engine, @simd_wat)
+      end
+    end
+
+    test "compiles modules without parallel compilation" do
+      {:ok, engine} = Engine.new(%EngineConfig{parallel_compilation: false})
+      assert {:ok, _module} = WasmexWasmtime.Module.compile(engine, @simd_wat)
+    end
+
+    test "errors on an unknown cranelift optimization level" do
+      assert {:error, "cranelift_opt_level must be one of :none, :speed, or :speed_and_size"} ==
+               Engine.new(%EngineConfig{cranelift_opt_level: :fastest})
+    end
+
+    test "errors on an invalid combination of wasm proposals" do
+      assert {:error, _reason} =
+               Engine.new(%EngineConfig{wasm_bulk_memory: false, wasm_reference_types: true})
+    end
+  end
+
+  describe "sharing an engine" do
+    test "a module compiled once can be instantiated in many stores" do
+      {:ok, engine} = Engine.new()
+      {:ok, module} = WasmexWasmtime.Module.compile(engine, "(module (func (export \"f\")))")
+
+      for _ <- 1..3 do
+        {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+        assert {:ok, _instance} = WasmexWasmtime.Instance.new(store, module, %{})
+      end
+    end
+
+    test "a module can not be instantiated in a store of another engine" do
+      {:ok, module} = WasmexWasmtime.Module.compile(Engine.new_default(), "(module)")
+      {:ok, store} = WasmexWasmtime.Store.new(engine: Engine.new_default())
+      assert {:error, _reason} = WasmexWasmtime.Instance.new(store, module, %{})
+    end
+  end
+
+  describe "precompile_module/2" do
+    test "precompiles a module which can be deserialized" do
+      {:ok, engine} = Engine.new()
+      {:ok, serialized} = Engine.precompile_module(engine, @simd_wat)
+
+      assert {:ok, module} = WasmexWasmtime.Module.unsafe_deserialize(serialized, engine)
+      assert %{"zero" => {:fn, [], [:v128]}} = WasmexWasmtime.Module.exports(module)
+    end
+
+    test "precompiles a module from an engine config" do
+      assert {:ok, _serialized} = Engine.precompile_module(%EngineConfig{}, @simd_wat)
+    end
+
+    test "cross-compiles for another target" do
+      target =
+        if :erlang.system_info(:system_architecture) |> to_string() =~ "aarch64",
+          do: "x86_64-unknown-linux-gnu",
+          else: "aarch64-unknown-linux-gnu"
+
+      {:ok, serialized} = Engine.precompile_module(%EngineConfig{target: target}, @simd_wat)
+
+      assert {:error, {:target, _message}} =
+               WasmexWasmtime.Module.check_compatibility(serialized, Engine.new_default())
+    end
+
+    test "errors on invalid bytes" do
+      assert {:error, _reason} = Engine.precompile_module(Engine.new_default(), "(module")
+    end
+
+    test "errors on an unknown target" do
+      assert {:error, _reason} = Engine.new(%EngineConfig{target: "not-a-target"})
+    end
+  end
+end
@@ -0,0 +1,117 @@
+defmodule WasmexWasmtime.GlobalTest do
+  use ExUnit.Case, async: true
+  doctest WasmexWasmtime.Global
+
+  alias WasmexWasmtime.Global
+
+  @global_wat """
+  (module
+    (global (export "version") i32 (i32.const 3))
+    (global (export "stack_pointer") (mut i64) (i64.const 1024))
+    (global (export "ratio") (mut f64) (f64.const 0.5))
+    (func (export "get_stack_pointer") (result i64) (global.get 1)))
+  """
+
+  defp build_instance() do
+    {:ok, store} = WasmexWasmtime.Store.new()
+    {:ok, module} = WasmexWasmtime.Module.compile(store, @global_wat)
+    {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+    %{store: store, instance: instance}
+  end
+
+  describe "from_instance/3" do
+    test "finds an exported global" do
+      %{store: store, instance: instance} = build_instance()
+      assert {:ok, %Global{}} = Global.from_instance(store, instance, "version")
+    end
+
+    test "errors for unknown globals" do
+      %{store: store, instance: instance} = build_instance()
+
+      assert {:error, "exported global `unknown` not found"} ==
+               Global.from_instance(store, instance, "unknown")
+    end
+  end
+
+  describe "get/2 and set/3" do
+    test "reads and writes a mutable global" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, global} = Global.from_instance(store, instance, "stack_pointer")
+
+      assert 1024 == Global.get(store, global)
+      assert :ok == Global.set(store, global, 2048)
+      assert 2048 == Global.get(store, global)
+    end
+
+    test "the guest sees written values" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, global} = Global.from_instance(store, instance, "stack_pointer")
+      :ok = Global.set(store, global, 4096)
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(
+          store,
+          instance,
+          "get_stack_pointer",
+          [],
+          :fake_from
+        )
+
+      assert_receive {:returned_function_call, {:ok, [4096]}, :fake_from}, 2000
+    end
+
+    test "reads floats" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, global} = Global.from_instance(store, instance, "ratio")
+
+      assert 0.5 == Global.get(store, global)
+      assert :ok == Global.set(store, global, 0.25)
+      assert 0.25 == Global.get(store, global)
+    end
+
+    test "errors when setting a const global" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, global} = Global.from_instance(store, instance, "version")
+
+      assert {:error, "Could not set global: the global is immutable (:const)"} ==
+               Global.set(store, global, 4)
+
+      assert 3 == Global.get(store, global)
+    end
+
+    test "errors when the value does not match the global type" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, global} = Global.from_instance(store, instance, "stack_pointer")
+
+      assert {:error, _reason} = Global.set(store, global, 1.5)
+    end
+
+    test "errors for globals of another store" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, global} = Global.from_instance(store, instance, "stack_pointer")
+      %{store: other_store} = build_instance()
+
+      assert {:error, "the global does not belong to this store"} ==
+               Global.get(other_store, global)
+
+      assert {:error, "the global does not belong to this store"} ==
+               Global.set(other_store, global, 1)
+
+      assert {:error, "the global does not belong to this store"} ==
+               Global.type(other_store, global)
+
+      assert 1024 == Global.get(store, global)
+    end
+  end
+
+  describe "type/2" do
+    test "returns the type of a global" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, version} = Global.from_instance(store, instance, "version")
+      {:ok, ratio} = Global.from_instance(store, instance, "ratio")
+
+      assert {:global, %{mutability: :const, type: :i32}} == Global.type(store, version)
+      assert {:global, %{mutability: :var, type: :f64}} == Global.type(store, ratio)
+    end
+  end
+end
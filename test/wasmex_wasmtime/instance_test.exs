@@ -79,6 +79,58 @@ defmodule WasmexWasmtime.InstanceTest do
     end
   end
 
+  describe "new/3 with global imports" do
+    @global_wat """
+    (module
+      (import "env" "answer" (global $answer i32))
+      (import "env" "counter" (global $counter (mut i64)))
+      (func (export "get_answer") (result i32) (global.get $answer))
+      (func (export "increment") (result i64)
+        (global.set $counter (i64.add (global.get $counter) (i64.const 1)))
+        (global.get $counter)))
+    """
+
+    test "instantiates an Instance with imported globals" do
+      imports = %{
+        env: %{
+          answer: {:global, :i32, 42, :const},
+          counter: {:global, :i64, 10, :var}
+        }
+      }
+
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @global_wat, imports: imports}})
+      assert {:ok, [42]} == WasmexWasmtime.call_function(instance, :get_answer, [])
+      assert {:ok, [11]} == WasmexWasmtime.call_function(instance, :increment, [])
+      assert {:ok, [12]} == WasmexWasmtime.call_function(instance, :increment, [])
+    end
+
+    test "errors when the mutability of an imported global does not match" do
+      imports = %{
+        "env" => %{
+          "answer" => {:global, :i32, 42, :const},
+          "counter" => {:global, :i64, 10, :const}
+        }
+      }
+
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @global_wat)
+      assert {:error, _reason} = WasmexWasmtime.Instance.new(store, module, imports)
+    end
+
+    test "errors when the value does not match the global type" do
+      imports = %{
+        "env" => %{
+          "answer" => {:global, :i32, 4.2, :const},
+          "counter" => {:global, :i64, 10, :var}
+        }
+      }
+
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @global_wat)
+      assert {:error, _reason} = WasmexWasmtime.Instance.new(store, module, imports)
+    end
+  end
+
   describe "function_export_exists/2" do
     test "returns whether a function export could be found in the wasm file" do
       %{store: store, instance: instance} = build_wasm_instance()
@@ -151,6 +203,57 @@ defmodule WasmexWasmtime.InstanceTest do
       end
     end
 
+    test "calling a function that never returns with a timeout" do
+      {:ok, engine} =
+        WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{epoch_interruption: true})
+
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+
+      {:ok, module} =
+        WasmexWasmtime.Module.compile(store, File.read!(TestHelper.wasm_test_file_path()))
+
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+
+      assert :ok ==
+               WasmexWasmtime.Instance.call_exported_function(
+                 store,
+                 instance,
+                 "endless_loop",
+                 [],
+                 :fake_from,
+                 timeout: 50
+               )
+
+      receive do
+        {:returned_function_call, {:error, :timeout}, :fake_from} -> nil
+      after
+        2000 ->
+          raise "message_expected"
+      end
+    end
+
+    test "calling a function with a timeout requires epoch interruption" do
+      %{store: store, instance: instance} = build_wasm_instance()
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(
+          store,
+          instance,
+          "arity_0",
+          [],
+          :fake_from,
+          timeout: 50
+        )
+
+      receive do
+        {:returned_function_call, {:error, reason}, :fake_from} ->
+          assert reason =~ "epoch_interruption"
+      after
+        2000 ->
+          raise "message_expected"
+      end
+    end
+
     test "calling an imported function which returns the wrong type" do
       imports = %{
         "env" =>
@@ -186,6 +289,57 @@ defmodule WasmexWasmtime.InstanceTest do
     end
   end
 
+  describe "imported function callbacks" do
+    test "trap when not returning within the callback timeout" do
+      imports = %{"env" => TestHelper.default_imported_functions_env_stringified()}
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+
+      {:ok, instance} =
+        WasmexWasmtime.Instance.new(store, module, imports, callback_timeout: 50)
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(
+          store,
+          instance,
+          "using_imported_sum3",
+          [1, 2, 3],
+          :fake_from
+        )
+
+      # the callback is never answered
+      assert_receive {:invoke_callback, "env", "imported_sum3", _context, [1, 2, 3], _token}
+
+      assert_receive {:returned_function_call, {:error, :callback_timeout}, :fake_from}, 2000
+    end
+
+    test "trap when the receiving process exited" do
+      imports = %{"env" => TestHelper.default_imported_functions_env_stringified()}
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+      test_pid = self()
+
+      # callbacks are sent to the process creating the instance
+      {pid, ref} =
+        spawn_monitor(fn ->
+          {:ok, instance} = WasmexWasmtime.Instance.new(store, module, imports)
+          send(test_pid, {:instance, instance})
+        end)
+
+      assert_receive {:instance, instance}
+      assert_receive {:DOWN, ^ref, :process, ^pid, :normal}
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(
+          store,
+          instance,
+          "using_imported_sum3",
+          [1, 2, 3],
+          :fake_from
+        )
+
+      assert_receive {:returned_function_call, {:error, :callback_abandoned}, :fake_from}, 2000
+    end
+  end
+
   describe "memory/3" do
     test "returns a memory struct" do
       %{store: store, instance: instance} = build_wasm_instance()
@@ -2,6 +2,12 @@ defmodule WasmexWasmtime.MemoryTest do
   use ExUnit.Case, async: true
   doctest WasmexWasmtime.Memory
 
+  # in bytes
+  @page_size 65_536
+  @initial_pages 17
+  # in bytes
+  @min_memory_size @initial_pages * @page_size
+
   defp build_wasm_instance do
     %{store: store, module: module} = TestHelper.wasm_module()
     {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
@@ -15,18 +21,177 @@ defmodule WasmexWasmtime.MemoryTest do
     %{store: store, module: module, instance: instance, memory: memory}
   end
 
-  describe "from_instance/1" do
+  @import_memory_wat """
+  (module
+    (import "env" "memory" (memory 1 2))
+    (func (export "first_byte") (result i32) (i32.load8_u (i32.const 0))))
+  """
+
+  describe "new/2" do
+    test "creates a memory in a store" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, memory} = WasmexWasmtime.Memory.new(store, %{minimum: 1, maximum: 2})
+
+      assert 65_536 == WasmexWasmtime.Memory.length(store, memory)
+      assert 1 == WasmexWasmtime.Memory.grow(store, memory, 1)
+      assert {:error, _reason} = WasmexWasmtime.Memory.grow(store, memory, 1)
+    end
+
+    test "a prefilled memory can be imported" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, memory} = WasmexWasmtime.Memory.new(store, %{minimum: 1, maximum: 2})
+      :ok = WasmexWasmtime.Memory.write_binary(store, memory, 0, "*")
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @import_memory_wat)
+      imports = %{"env" => %{"memory" => {:memory, memory}}}
+
+      instance =
+        start_supervised!({WasmexWasmtime, %{store: store, module: module, imports: imports}})
+
+      assert {:ok, [?*]} == WasmexWasmtime.call_function(instance, :first_byte, [])
+    end
+
+    test "a memory is created from an import definition" do
+      imports = %{env: %{memory: {:memory, %{minimum: 1, maximum: 2}}}}
+
+      instance =
+        start_supervised!({WasmexWasmtime, %{bytes: @import_memory_wat, imports: imports}})
+
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :first_byte, [])
+    end
+
+    test "errors when the imported memory is too small" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @import_memory_wat)
+      imports = %{"env" => %{"memory" => {:memory, %{minimum: 0}}}}
+
+      assert {:error, _reason} = WasmexWasmtime.Instance.new(store, module, imports)
+    end
+
+    test "shared memories can only be created as imports" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+
+      assert {:error, _reason} =
+               WasmexWasmtime.Memory.new(store, %{minimum: 1, maximum: 2, shared: true})
+    end
+  end
+
+  @multi_memory_wat """
+  (module
+    (import "env" "read_bytes" (func $read_bytes (result i32)))
+    (memory (export "heap") 1)
+    (memory (export "scratch") 2)
+    (data (memory 0) (i32.const 0) "h")
+    (data (memory 1) (i32.const 0) "s")
+    (func (export "call_read_bytes") (result i32) (call $read_bytes)))
+  """
+
+  defp build_multi_memory_instance(imports) do
+    {:ok, engine} =
+      WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{wasm_multi_memory: true})
+
+    {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+    {:ok, module} = WasmexWasmtime.Module.compile(store, @multi_memory_wat)
+    {:ok, instance} = WasmexWasmtime.Instance.new(store, module, imports)
+    %{store: store, module: module, instance: instance}
+  end
+
+  @read_bytes_import %{
+    "env" => %{"read_bytes" => {:fn, [], [:i32], fn _context -> 0 end}}
+  }
+
+  describe "from_instance/3" do
     test "creates memory with defaults for size and offset" do
       %{store: store, instance: instance} = build_wasm_instance()
       {:ok, %WasmexWasmtime.Memory{}} = WasmexWasmtime.Memory.from_instance(store, instance)
     end
+
+    test "prefers the memory exported as \"memory\"" do
+      wat = """
+      (module
+        (memory (export "heap") 1)
+        (memory (export "memory") 2))
+      """
+
+      {:ok, engine} =
+        WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{wasm_multi_memory: true})
+
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, wat)
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+
+      {:ok, memory} = WasmexWasmtime.Memory.from_instance(store, instance)
+      assert 2 * @page_size == WasmexWasmtime.Memory.length(store, memory)
+    end
+
+    test "selects a memory by export name" do
+      %{store: store, instance: instance} = build_multi_memory_instance(@read_bytes_import)
+
+      {:ok, heap} = WasmexWasmtime.Memory.from_instance(store, instance, "heap")
+      {:ok, scratch} = WasmexWasmtime.Memory.from_instance(store, instance, "scratch")
+
+      assert "h" == WasmexWasmtime.Memory.read_binary(store, heap, 0, 1)
+      assert "s" == WasmexWasmtime.Memory.read_binary(store, scratch, 0, 1)
+      assert 2 * @page_size == WasmexWasmtime.Memory.length(store, scratch)
+    end
+
+    test "errors for unknown memories" do
+      %{store: store, instance: instance} = build_multi_memory_instance(@read_bytes_import)
+
+      assert {:error, "exported memory `unknown` not found"} ==
+               WasmexWasmtime.Memory.from_instance(store, instance, "unknown")
+    end
   end
 
-  # in bytes
-  @page_size 65_536
-  @initial_pages 17
-  # in bytes
-  @min_memory_size @initial_pages * @page_size
+  describe "export_names/2" do
+    test "lists all exported memories" do
+      %{store: store, instance: instance} = build_multi_memory_instance(@read_bytes_import)
+      assert ["heap", "scratch"] == WasmexWasmtime.Memory.export_names(store, instance)
+    end
+
+    test "returns an empty list for modules without memory" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, "(module)")
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+
+      assert [] == WasmexWasmtime.Memory.export_names(store, instance)
+    end
+  end
+
+  describe "callback context" do
+    test "contains all memories of the calling instance by export name" do
+      test_pid = self()
+
+      imports = %{
+        "env" => %{
+          "read_bytes" =>
+            {:fn, [], [:i32],
+             fn %{caller: caller, memory: memory, memories: memories} ->
+               send(test_pid, {
+                 :memories,
+                 WasmexWasmtime.Memory.read_binary(caller, memory, 0, 1),
+                 Map.new(memories, fn {name, memory} ->
+                   {name, WasmexWasmtime.Memory.read_binary(caller, memory, 0, 1)}
+                 end)
+               })
+
+               0
+             end}
+        }
+      }
+
+      {:ok, engine} =
+        WasmexWasmtime.Engine.new(%WasmexWasmtime.EngineConfig{wasm_multi_memory: true})
+
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @multi_memory_wat)
+
+      instance =
+        start_supervised!({WasmexWasmtime, %{store: store, module: module, imports: imports}})
+
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :call_read_bytes, [])
+      assert_receive {:memories, "h", %{"heap" => "h", "scratch" => "s"}}, 2000
+    end
+  end
 
   describe "length/1" do
     test "returns number of uint8 elements that fit into memory" do
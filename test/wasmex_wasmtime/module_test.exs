@@ -2,6 +2,8 @@ defmodule WasmexWasmtime.ModuleTest do
   use ExUnit.Case, async: true
   doctest WasmexWasmtime.Module
 
+  alias WasmexWasmtime.Engine
+
   @wat """
   (module
     (type $add_one_t (func (param i32) (result i32)))
@@ -20,6 +22,21 @@ defmodule WasmexWasmtime.ModuleTest do
       assert {:ok, [42]} == WasmexWasmtime.call_function(instance, :add_one, [41])
     end
 
+    test "compiles with a store running a call" do
+      {:ok, engine} = Engine.new(%WasmexWasmtime.EngineConfig{epoch_interruption: true})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      endless_loop = "(module (func (export \"run\") (loop $l (br $l))))"
+      {:ok, module} = WasmexWasmtime.Module.compile(store, endless_loop)
+      instance = start_supervised!({WasmexWasmtime, %{module: module, store: store}})
+
+      task = Task.async(fn -> WasmexWasmtime.call_function(instance, :run, []) end)
+      Process.sleep(50)
+      assert {:ok, _module} = WasmexWasmtime.Module.compile(store, @wat)
+
+      :ok = WasmexWasmtime.cancel_call(instance)
+      assert {:error, :cancelled} == Task.await(task)
+    end
+
     test "errors when attempting to compile nonsense" do
       wat = "wat is this? not WAT for sure"
       {:ok, store} = WasmexWasmtime.Store.new()
@@ -32,13 +49,169 @@ defmodule WasmexWasmtime.ModuleTest do
 
   describe "module de-/serialization" do
     test "a module can be serialized and deserialized again" do
-      %{module: module} = TestHelper.wasm_module()
+      %{store: store, module: module} = TestHelper.wasm_module()
       {:ok, serialized} = WasmexWasmtime.Module.serialize(module)
-      {:ok, deserialized_module} = WasmexWasmtime.Module.unsafe_deserialize(serialized)
+      {:ok, deserialized_module} = WasmexWasmtime.Module.unsafe_deserialize(serialized, store)
 
       assert WasmexWasmtime.Module.exports(module) ==
                WasmexWasmtime.Module.exports(deserialized_module)
     end
+
+    test "a module can be deserialized into a given engine" do
+      engine = WasmexWasmtime.Engine.new_default()
+      {:ok, module} = WasmexWasmtime.Module.compile(engine, @wat)
+      {:ok, serialized} = WasmexWasmtime.Module.serialize(module)
+      {:ok, deserialized_module} = WasmexWasmtime.Module.unsafe_deserialize(serialized, engine)
+
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      instance = start_supervised!({WasmexWasmtime, %{module: deserialized_module, store: store}})
+      assert {:ok, [42]} == WasmexWasmtime.call_function(instance, :add_one, [41])
+    end
+
+    test "a module can be deserialized into the engine of a store" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @wat)
+      {:ok, serialized} = WasmexWasmtime.Module.serialize(module)
+      {:ok, deserialized_module} = WasmexWasmtime.Module.unsafe_deserialize(serialized, store)
+
+      instance = start_supervised!({WasmexWasmtime, %{module: deserialized_module, store: store}})
+      assert {:ok, [42]} == WasmexWasmtime.call_function(instance, :add_one, [41])
+    end
+  end
+
+  describe "loading from files" do
+    @tag :tmp_dir
+    test "compiles a module from a wat file", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "add_one.wat")
+      File.write!(path, @wat)
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, module} = WasmexWasmtime.Module.compile_file(store, path)
+
+      instance = start_supervised!({WasmexWasmtime, %{module: module, store: store}})
+      assert {:ok, [42]} == WasmexWasmtime.call_function(instance, :add_one, [41])
+    end
+
+    test "compiles a module from a wasm file" do
+      {:ok, module} =
+        WasmexWasmtime.Module.compile_file(Engine.new_default(), TestHelper.wasm_test_file_path())
+
+      assert %{"arity_0" => {:fn, [], [:i32]}} = WasmexWasmtime.Module.exports(module)
+    end
+
+    test "compiling a missing file errors" do
+      assert {:error, _reason} =
+               WasmexWasmtime.Module.compile_file(Engine.new_default(), "does/not/exist.wasm")
+    end
+
+    @tag :tmp_dir
+    test "deserializes a precompiled module from a file", %{tmp_dir: tmp_dir} do
+      engine = Engine.new_default()
+      {:ok, serialized} = Engine.precompile_module(engine, @wat)
+      path = Path.join(tmp_dir, "add_one.cwasm")
+      File.write!(path, serialized)
+
+      {:ok, module} = WasmexWasmtime.Module.unsafe_deserialize_file(path, engine)
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      instance = start_supervised!({WasmexWasmtime, %{module: module, store: store}})
+      assert {:ok, [42]} == WasmexWasmtime.call_function(instance, :add_one, [41])
+    end
+  end
+
+  describe "validate/2" do
+    test "accepts a valid module" do
+      assert :ok == WasmexWasmtime.Module.validate(Engine.new_default(), @wat)
+      wasm = File.read!(TestHelper.wasm_test_file_path())
+      assert :ok == WasmexWasmtime.Module.validate(Engine.new_default(), wasm)
+    end
+
+    test "reports the position of WAT syntax errors" do
+      assert {:error, error} =
+               WasmexWasmtime.Module.validate(Engine.new_default(), "(module\n  (func nonsense))")
+
+      assert %{message: message, line: 2, column: column, offset: offset, feature: nil} = error
+
+      assert is_binary(message)
+      # "(module\n" is 8 bytes long
+      assert offset == 8 + column - 1
+    end
+
+    test "reports the offset of invalid wasm" do
+      invalid_wasm = <<0, 97, 115, 109, 1, 0, 0, 0, 99>>
+
+      assert {:error, %{offset: offset, line: nil, column: nil}} =
+               WasmexWasmtime.Module.validate(Engine.new_default(), invalid_wasm)
+
+      assert is_integer(offset)
+    end
+
+    test "reports disabled proposals" do
+      {:ok, engine} = Engine.new(%WasmexWasmtime.EngineConfig{wasm_simd: false})
+      simd_wat = "(module (func (export \"zero\") (result v128) v128.const i32x4 0 0 0 0))"
+
+      assert {:error, %{feature: :simd, message: message}} =
+               WasmexWasmtime.Module.validate(engine, simd_wat)
+
+      assert message =~ "SIMD"
+    end
+  end
+
+  describe "check_compatibility/2" do
+    test "a serialized module is compatible with an engine of the same config" do
+      {:ok, module} = WasmexWasmtime.Module.compile(Engine.new_default(), @wat)
+      {:ok, serialized} = WasmexWasmtime.Module.serialize(module)
+
+      assert :ok == WasmexWasmtime.Module.check_compatibility(serialized, Engine.new_default())
+    end
+
+    test "a serialized module is incompatible with an engine of a different config" do
+      {:ok, module} = WasmexWasmtime.Module.compile(Engine.new_default(), @wat)
+      {:ok, serialized} = WasmexWasmtime.Module.serialize(module)
+      {:ok, engine} = Engine.new(%WasmexWasmtime.EngineConfig{wasm_simd: false})
+
+      assert {:error, {:config, message}} =
+               WasmexWasmtime.Module.check_compatibility(serialized, engine)
+
+      assert message =~ "SIMD"
+    end
+
+    test "a serialized module is incompatible with another wasmtime version" do
+      {:ok, module} = WasmexWasmtime.Module.compile(Engine.new_default(), @wat)
+      {:ok, serialized} = WasmexWasmtime.Module.serialize(module)
+      # the wasmtime version the module was serialized with is embedded as string
+      other_version = :binary.replace(serialized, "4.0.0", "0.0.1")
+
+      assert {:error, {:version, message}} =
+               WasmexWasmtime.Module.check_compatibility(other_version, Engine.new_default())
+
+      assert message =~ "0.0.1"
+    end
+
+    test "a serialized module is incompatible with another target" do
+      target =
+        if :erlang.system_info(:system_architecture) |> to_string() =~ "aarch64",
+          do: "x86_64-unknown-linux-gnu",
+          else: "aarch64-unknown-linux-gnu"
+
+      {:ok, serialized} =
+        Engine.precompile_module(%WasmexWasmtime.EngineConfig{target: target}, @wat)
+
+      assert {:error, {:target, _message}} =
+               WasmexWasmtime.Module.check_compatibility(serialized, Engine.new_default())
+    end
+
+    test "arbitrary bytes are invalid" do
+      assert {:error, {:invalid, _message}} =
+               WasmexWasmtime.Module.check_compatibility("not a module", Engine.new_default())
+    end
+
+    test "unrecognized incompatibilities fall back to a generic kind" do
+      {:ok, module} = WasmexWasmtime.Module.compile(Engine.new_default(), @wat)
+      {:ok, serialized} = WasmexWasmtime.Module.serialize(module)
+      truncated = binary_part(serialized, 0, 64)
+
+      assert {:error, {:incompatible, _message}} =
+               WasmexWasmtime.Module.check_compatibility(truncated, Engine.new_default())
+    end
   end
 
   describe "name/1" do
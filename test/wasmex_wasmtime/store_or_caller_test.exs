@@ -0,0 +1,147 @@
+defmodule WasmexWasmtime.StoreOrCallerTest do
+  use ExUnit.Case, async: true
+  doctest WasmexWasmtime.StoreOrCaller
+
+  alias WasmexWasmtime.{Engine, EngineConfig, StoreOrCaller}
+
+  @loop_wat """
+  (module
+    (func (export "count_down") (param $n i32) (result i32)
+      (loop $continue
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br_if $continue (i32.gt_s (local.get $n) (i32.const 0))))
+      (local.get $n)))
+  """
+
+  @endless_loop_wat """
+  (module
+    (func (export "run")
+      (loop $l (br $l)))
+    (func (export "count_down") (param $n i32) (result i32)
+      (loop $continue
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br_if $continue (i32.gt_s (local.get $n) (i32.const 0))))
+      (local.get $n)))
+  """
+
+  defp fuel_store() do
+    {:ok, engine} = Engine.new(%EngineConfig{consume_fuel: true})
+    {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+    store
+  end
+
+  describe "fuel" do
+    test "adds fuel and reads the remaining fuel" do
+      store = fuel_store()
+      assert {:ok, 0} == StoreOrCaller.fuel_remaining(store)
+      assert :ok == StoreOrCaller.add_fuel(store, 1_000)
+      assert {:ok, 1_000} == StoreOrCaller.fuel_remaining(store)
+      assert {:ok, 0} == StoreOrCaller.fuel_consumed(store)
+    end
+
+    test "executing a function consumes fuel" do
+      store = fuel_store()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @loop_wat)
+      :ok = StoreOrCaller.add_fuel(store, 10_000)
+      instance = start_supervised!({WasmexWasmtime, %{store: store, module: module}})
+
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :count_down, [10])
+      {:ok, consumed} = StoreOrCaller.fuel_consumed(store)
+      assert consumed > 0
+      assert {:ok, 10_000 - consumed} == StoreOrCaller.fuel_remaining(store)
+    end
+
+    test "a call running out of fuel returns a distinct error" do
+      store = fuel_store()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @loop_wat)
+      :ok = StoreOrCaller.add_fuel(store, 100)
+      instance = start_supervised!({WasmexWasmtime, %{store: store, module: module}})
+
+      assert {:error, :out_of_fuel} ==
+               WasmexWasmtime.call_function(instance, :count_down, [1_000_000])
+    end
+
+    test "fuel can not be read when fuel consumption is disabled" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      assert {:error, _reason} = StoreOrCaller.fuel_consumed(store)
+      assert {:error, _reason} = StoreOrCaller.fuel_remaining(store)
+      assert {:error, _reason} = StoreOrCaller.add_fuel(store, 10)
+    end
+  end
+
+  describe "engine/1" do
+    test "returns the engine of a store running a call" do
+      {:ok, engine} = Engine.new(%EngineConfig{epoch_interruption: true})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @endless_loop_wat)
+      instance = start_supervised!({WasmexWasmtime, %{store: store, module: module}})
+
+      task = Task.async(fn -> WasmexWasmtime.call_function(instance, :run, []) end)
+      Process.sleep(50)
+      assert {:ok, %Engine{resource: resource}} = StoreOrCaller.engine(store)
+      assert resource == engine.resource
+
+      :ok = WasmexWasmtime.cancel_call(instance)
+      assert {:error, :cancelled} == Task.await(task)
+    end
+  end
+
+  describe "cancel_call/2" do
+    test "cancels a running call" do
+      {:ok, engine} = Engine.new(%EngineConfig{epoch_interruption: true})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @endless_loop_wat)
+      instance = start_supervised!({WasmexWasmtime, %{store: store, module: module}})
+
+      task = Task.async(fn -> WasmexWasmtime.call_function(instance, :run, []) end)
+      Process.sleep(50)
+      assert :ok == WasmexWasmtime.cancel_call(instance)
+      assert {:error, :cancelled} == Task.await(task)
+    end
+
+    test "does not cancel the next call once the cancelled call returned" do
+      {:ok, engine} = Engine.new(%EngineConfig{epoch_interruption: true})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @endless_loop_wat)
+      instance = start_supervised!({WasmexWasmtime, %{store: store, module: module}})
+
+      task = Task.async(fn -> WasmexWasmtime.call_function(instance, :run, []) end)
+      Process.sleep(50)
+      assert :ok == WasmexWasmtime.cancel_call(instance)
+      assert {:error, :cancelled} == Task.await(task)
+
+      # a late cancel, with no call running
+      assert :ok == StoreOrCaller.cancel_call(store)
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :count_down, [50_000_000])
+    end
+
+    test "cancels the call started with the given from" do
+      {:ok, engine} = Engine.new(%EngineConfig{epoch_interruption: true})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @endless_loop_wat)
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+
+      ref = make_ref()
+      :ok = WasmexWasmtime.Instance.call_exported_function(store, instance, "run", [], ref)
+      Process.sleep(50)
+      assert :ok == StoreOrCaller.cancel_call(store, make_ref())
+      assert :ok == StoreOrCaller.cancel_call(store, ref)
+      assert_receive {:returned_function_call, {:error, :cancelled}, ^ref}, 1000
+    end
+
+    test "interrupts a call once the timeout of call_function/4 is reached" do
+      {:ok, engine} = Engine.new(%EngineConfig{epoch_interruption: true})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @endless_loop_wat)
+      instance = start_supervised!({WasmexWasmtime, %{store: store, module: module}})
+
+      catch_exit(WasmexWasmtime.call_function(instance, :run, [], 100))
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :count_down, [1])
+    end
+
+    test "requires epoch interruption" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      assert {:error, _reason} = StoreOrCaller.cancel_call(store)
+    end
+  end
+end
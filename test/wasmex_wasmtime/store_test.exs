@@ -0,0 +1,145 @@
+defmodule WasmexWasmtime.StoreTest do
+  use ExUnit.Case, async: true
+  doctest WasmexWasmtime.Store
+
+  alias WasmexWasmtime.{Engine, EngineConfig, StoreLimits}
+
+  @simd_wat """
+  (module
+    (func (export "zero") (result v128)
+      v128.const i32x4 0 0 0 0))
+  """
+
+  @memory_wat """
+  (module
+    (memory (export "memory") 1)
+    (func (export "grow") (param $pages i32) (result i32)
+      (memory.grow (local.get $pages))))
+  """
+
+  defp answer_growth_requests(allowed) do
+    receive do
+      {:memory_growing, %{current: 65_536, desired: 131_072, maximum: nil}, token} ->
+        :ok = StoreLimits.respond_growth(token, allowed)
+    after
+      2000 -> raise "growth request expected"
+    end
+  end
+
+  describe "new/1" do
+    test "creates a store with a new default engine" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      assert {:ok, _module} = WasmexWasmtime.Module.compile(store, @simd_wat)
+    end
+
+    test "creates a store from the given engine" do
+      {:ok, engine} = Engine.new(%EngineConfig{wasm_simd: false})
+      {:ok, store} = WasmexWasmtime.Store.new(engine: engine)
+      assert {:error, reason} = WasmexWasmtime.Module.compile(store, @simd_wat)
+      assert reason =~ "SIMD support is not enabled"
+    end
+  end
+
+  describe "new_wasi/2" do
+    test "creates a WASI store from the given engine" do
+      {:ok, engine} = Engine.new(%EngineConfig{wasm_simd: false})
+      {:ok, store} =
+        WasmexWasmtime.Store.new_wasi(%WasmexWasmtime.Wasi.WasiOptions{}, engine: engine)
+      assert {:error, _reason} = WasmexWasmtime.Module.compile(store, @simd_wat)
+    end
+  end
+
+  describe "store limits" do
+    test "growing memory beyond the limit fails" do
+      {:ok, store} = WasmexWasmtime.Store.new(limits: %StoreLimits{memory_size: 2 * 65_536})
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @memory_wat)
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+      {:ok, memory} = WasmexWasmtime.Memory.from_instance(store, instance)
+
+      assert 1 == WasmexWasmtime.Memory.grow(store, memory, 1)
+
+      assert {:error, {:resource_limit_exceeded, :memory}} ==
+               WasmexWasmtime.Memory.grow(store, memory, 1)
+    end
+
+    test "memory.grow beyond the limit fails within a call" do
+      limits = %StoreLimits{memory_size: 2 * 65_536}
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @memory_wat, store_limits: limits}})
+
+      assert {:ok, [1]} == WasmexWasmtime.call_function(instance, :grow, [1])
+      assert {:ok, [-1]} == WasmexWasmtime.call_function(instance, :grow, [1])
+    end
+
+    test "instantiating a module with a memory beyond the limit fails" do
+      {:ok, store} = WasmexWasmtime.Store.new(limits: %StoreLimits{memory_size: 1_000})
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @memory_wat)
+
+      assert {:error, {:resource_limit_exceeded, :memory}} ==
+               WasmexWasmtime.Instance.new(store, module, %{})
+    end
+
+    test "creating more instances than allowed fails" do
+      {:ok, store} = WasmexWasmtime.Store.new(limits: %StoreLimits{instances: 1})
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @memory_wat)
+
+      assert {:ok, _instance} = WasmexWasmtime.Instance.new(store, module, %{})
+
+      assert {:error, {:resource_limit_exceeded, :instances}} ==
+               WasmexWasmtime.Instance.new(store, module, %{})
+    end
+  end
+
+  describe "growth handler" do
+    test "memory growth is allowed by the growth handler" do
+      {:ok, store} = WasmexWasmtime.Store.new(limits: %StoreLimits{growth_handler: self()})
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @memory_wat)
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(
+          store,
+          instance,
+          "grow",
+          [1],
+          :fake_from
+        )
+
+      answer_growth_requests(true)
+      assert_receive {:returned_function_call, {:ok, [1]}, :fake_from}, 2000
+    end
+
+    test "memory growth is denied by the growth handler" do
+      {:ok, store} = WasmexWasmtime.Store.new(limits: %StoreLimits{growth_handler: self()})
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @memory_wat)
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(
+          store,
+          instance,
+          "grow",
+          [1],
+          :fake_from
+        )
+
+      answer_growth_requests(false)
+
+      assert_receive {:returned_function_call, {:ok, [-1]}, :fake_from}, 2000
+    end
+
+    test "memory growth is denied if the growth handler does not respond in time" do
+      limits = %StoreLimits{growth_handler: self(), growth_timeout: 50}
+      {:ok, store} = WasmexWasmtime.Store.new(limits: limits)
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @memory_wat)
+      {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+      # the callback timeout of other instances does not change the growth timeout
+      {:ok, _instance} = WasmexWasmtime.Instance.new(store, module, %{}, callback_timeout: 60_000)
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(store, instance, "grow", [1], :fake_from)
+
+      assert_receive {:memory_growing, _request, _token}, 2000
+      assert_receive {:returned_function_call, {:ok, [-1]}, :fake_from}, 2000
+    end
+  end
+end
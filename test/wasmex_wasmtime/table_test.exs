@@ -0,0 +1,222 @@
+defmodule WasmexWasmtime.TableTest do
+  use ExUnit.Case, async: true
+  doctest WasmexWasmtime.Table
+
+  alias WasmexWasmtime.Table
+
+  @import_table_wat """
+  (module
+    (import "env" "__indirect_function_table" (table 2 funcref))
+    (func (export "table_size") (result i32) (table.size 0)))
+  """
+
+  @export_table_wat """
+  (module
+    (table (export "functions") 2 4 funcref)
+    (table (export "refs") 1 externref)
+    (func $add (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+    (func $sub (param i32 i32) (result i32) (i32.sub (local.get 0) (local.get 1)))
+    (elem (i32.const 0) $add)
+    (func (export "call_indirect") (param i32 i32 i32) (result i32)
+      (call_indirect (param i32 i32) (result i32) (local.get 1) (local.get 2) (local.get 0)))
+    (export "sub" (func $sub)))
+  """
+
+  defp build_instance() do
+    {:ok, store} = WasmexWasmtime.Store.new()
+    {:ok, module} = WasmexWasmtime.Module.compile(store, @export_table_wat)
+    {:ok, instance} = WasmexWasmtime.Instance.new(store, module, %{})
+    %{store: store, instance: instance}
+  end
+
+  describe "new/2" do
+    test "creates a funcref table" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, table} = Table.new(store, %{minimum: 2, maximum: 10, type: :func_ref})
+
+      assert 2 == Table.size(store, table)
+      assert {:table, %{minimum: 2, maximum: 10, type: :func_ref}} == Table.type(store, table)
+    end
+
+    test "creates an externref table without maximum" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, table} = Table.new(store, %{minimum: 0, type: :extern_ref})
+
+      assert 0 == Table.size(store, table)
+      assert {:table, %{minimum: 0, type: :extern_ref}} == Table.type(store, table)
+    end
+
+    test "errors on an unknown element type" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      assert {:error, _reason} = Table.new(store, %{minimum: 0, type: :i32})
+    end
+  end
+
+  describe "table imports" do
+    test "imports a table created up front" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, table} = Table.new(store, %{minimum: 3, type: :func_ref})
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @import_table_wat)
+      imports = %{"env" => %{"__indirect_function_table" => {:table, table}}}
+
+      instance =
+        start_supervised!({WasmexWasmtime, %{store: store, module: module, imports: imports}})
+
+      assert {:ok, [3]} == WasmexWasmtime.call_function(instance, :table_size, [])
+    end
+
+    test "imports a table from a table type" do
+      imports = %{
+        env: %{__indirect_function_table: {:table, %{minimum: 2, type: :func_ref}}}
+      }
+
+      instance =
+        start_supervised!({WasmexWasmtime, %{bytes: @import_table_wat, imports: imports}})
+
+      assert {:ok, [2]} == WasmexWasmtime.call_function(instance, :table_size, [])
+    end
+
+    test "errors when the imported table has the wrong element type" do
+      {:ok, store} = WasmexWasmtime.Store.new()
+      {:ok, module} = WasmexWasmtime.Module.compile(store, @import_table_wat)
+      imports = %{
+        "env" => %{"__indirect_function_table" => {:table, %{minimum: 2, type: :extern_ref}}}
+      }
+
+      assert {:error, _reason} = WasmexWasmtime.Instance.new(store, module, imports)
+    end
+  end
+
+  describe "from_instance/3" do
+    test "finds an exported table" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+
+      assert 2 == Table.size(store, table)
+      assert {:table, %{minimum: 2, maximum: 4, type: :func_ref}} == Table.type(store, table)
+    end
+
+    test "errors for unknown tables" do
+      %{store: store, instance: instance} = build_instance()
+
+      assert {:error, "exported table `unknown` not found"} ==
+               Table.from_instance(store, instance, "unknown")
+    end
+  end
+
+  describe "get/3 and set/4" do
+    test "returns func-refs as callable function handles" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+
+      assert {:ok, %WasmexWasmtime.Function{} = add} = Table.get(store, table, 0)
+      assert {:ok, nil} == Table.get(store, table, 1)
+
+      :ok = WasmexWasmtime.Function.call(store, add, [3, 4], :fake_from)
+      assert_receive {:returned_function_call, {:ok, [7]}, :fake_from}, 2000
+    end
+
+    test "function handles can not be called with another store" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+      {:ok, add} = Table.get(store, table, 0)
+      %{store: other_store} = build_instance()
+
+      :ok = WasmexWasmtime.Function.call(other_store, add, [3, 4], :fake_from)
+      assert_receive {:returned_function_call, {:error, _reason}, :fake_from}, 2000
+
+      :ok = WasmexWasmtime.Function.call(store, add, [3, 4], :fake_from)
+      assert_receive {:returned_function_call, {:ok, [7]}, :fake_from}, 2000
+    end
+
+    test "tables can not be used with another store" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+      %{store: other_store} = build_instance()
+      error = {:error, "the table does not belong to this store"}
+
+      assert error == Table.size(other_store, table)
+      assert error == Table.type(other_store, table)
+      assert error == Table.get(other_store, table, 0)
+      assert error == Table.set(other_store, table, 0, nil)
+      assert error == Table.grow(other_store, table, 1)
+
+      assert {:ok, %WasmexWasmtime.Function{}} = Table.get(store, table, 0)
+    end
+
+    test "patches the indirect call table" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+      {:ok, add} = Table.get(store, table, 0)
+
+      assert :ok == Table.set(store, table, 1, add)
+
+      :ok =
+        WasmexWasmtime.Instance.call_exported_function(
+          store,
+          instance,
+          "call_indirect",
+          [1, 5, 3],
+          :fake_from
+        )
+
+      assert_receive {:returned_function_call, {:ok, [8]}, :fake_from}, 2000
+
+      assert :ok == Table.set(store, table, 0, nil)
+      assert {:ok, nil} == Table.get(store, table, 0)
+    end
+
+    test "stores elixir terms in extern-ref tables" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "refs")
+      term = %{pid: self(), tags: [:a, "b"]}
+
+      assert {:ok, nil} == Table.get(store, table, 0)
+      assert :ok == Table.set(store, table, 0, term)
+      assert {:ok, term} == Table.get(store, table, 0)
+    end
+
+    test "errors for indices out of bounds" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+
+      assert {:error, "table index 2 out of bounds"} == Table.get(store, table, 2)
+      assert {:error, _reason} = Table.set(store, table, 2, nil)
+    end
+
+    test "errors when setting a func-ref element to something other than a function" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+
+      assert {:error, _reason} = Table.set(store, table, 0, 42)
+    end
+  end
+
+  describe "grow/4" do
+    test "grows the table and returns the previous size" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+      {:ok, add} = Table.get(store, table, 0)
+
+      assert 2 == Table.grow(store, table, 1, add)
+      assert 3 == Table.size(store, table)
+      assert {:ok, %WasmexWasmtime.Function{}} = Table.get(store, table, 2)
+    end
+
+    test "errors when growing beyond the maximum" do
+      %{store: store, instance: instance} = build_instance()
+      {:ok, table} = Table.from_instance(store, instance, "functions")
+
+      assert {:error, _reason} = Table.grow(store, table, 3)
+      assert 2 == Table.size(store, table)
+    end
+
+    test "errors when growing beyond the store limits" do
+      limits = %WasmexWasmtime.StoreLimits{table_elements: 2}
+      {:ok, store} = WasmexWasmtime.Store.new(limits: limits)
+      {:ok, table} = Table.new(store, %{minimum: 2, type: :extern_ref})
+
+      assert {:error, {:resource_limit_exceeded, :table}} == Table.grow(store, table, 1)
+    end
+  end
+end
@@ -151,6 +151,227 @@ defmodule WasmexWasmtimeTest do
     assert 23 == WasmexWasmtime.Memory.get_byte(store, memory, 0)
   end
 
+  describe "when instantiating a module without exported memory" do
+    @no_memory_wat """
+    (module
+      (import "env" "double" (func $double (param i32) (result i32)))
+      (func (export "call_double") (param i32) (result i32) (call $double (local.get 0))))
+    """
+
+    test "imported functions can be called and get a nil memory" do
+      test_pid = self()
+
+      imports = %{
+        env: %{
+          double:
+            {:fn, [:i32], [:i32],
+             fn context, value ->
+               send(test_pid, {:context_memory, context.memory, context.memories})
+               value * 2
+             end}
+        }
+      }
+
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @no_memory_wat, imports: imports}})
+
+      assert {:ok, [42]} == WasmexWasmtime.call_function(instance, :call_double, [21])
+      assert_receive {:context_memory, nil, memories}
+      assert memories == %{}
+    end
+  end
+
+  describe "the callback context" do
+    @exports_wat """
+    (module
+      (import "env" "inspect" (func $inspect (result i32)))
+      (global (export "heap_base") (mut i32) (i32.const 1024))
+      (table (export "functions") 1 funcref)
+      (memory (export "memory") 1)
+      (func (export "call_inspect") (result i32) (call $inspect)))
+    """
+
+    test "contains the exported globals, tables, and functions of the calling instance" do
+      test_pid = self()
+
+      imports = %{
+        env: %{
+          inspect:
+            {:fn, [], [:i32],
+             fn %{caller: caller, globals: globals, tables: tables, functions: functions} ->
+               %{"heap_base" => heap_base} = globals
+               %{"functions" => table} = tables
+               table_size = WasmexWasmtime.Table.size(caller, table)
+               send(test_pid, {:exports, Map.keys(functions), table_size})
+
+               :ok = WasmexWasmtime.Global.set(caller, heap_base, 2048)
+               WasmexWasmtime.Global.get(caller, heap_base)
+             end}
+        }
+      }
+
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @exports_wat, imports: imports}})
+
+      assert {:ok, [2048]} == WasmexWasmtime.call_function(instance, :call_inspect, [])
+      assert_receive {:exports, ["call_inspect"], 1}
+    end
+  end
+
+  describe "re-entrant calls from callbacks" do
+    @reentrant_wat """
+    (module
+      (import "env" "fill" (func $fill (param i32) (result i32)))
+      (import "env" "log" (func $log (param i32)))
+      (memory (export "memory") 1)
+      (global $next (mut i32) (i32.const 16))
+      (func (export "alloc") (param $size i32) (result i32)
+        (local $pointer i32)
+        (local.set $pointer (global.get $next))
+        (global.set $next (i32.add (global.get $next) (local.get $size)))
+        (call $log (local.get $pointer))
+        (local.get $pointer))
+      (func (export "fill_and_read") (result i32)
+        (i32.load8_u (call $fill (i32.const 4)))))
+    """
+
+    test "callbacks call exported functions of the paused instance" do
+      test_pid = self()
+
+      imports = %{
+        env: %{
+          fill:
+            {:fn, [:i32], [:i32],
+             fn context, size ->
+               {:ok, [pointer]} =
+                 WasmexWasmtime.call_function_from_callback(context, :alloc, [size])
+
+               %{caller: caller, memory: memory} = context
+               :ok = WasmexWasmtime.Memory.write_binary(caller, memory, pointer, "*")
+               pointer
+             end},
+          log:
+            {:fn, [:i32], [],
+             fn _context, pointer ->
+               send(test_pid, {:allocated, pointer})
+               nil
+             end}
+        }
+      }
+
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @reentrant_wat, imports: imports}})
+
+      assert {:ok, [?*]} == WasmexWasmtime.call_function(instance, :fill_and_read, [])
+      assert_receive {:allocated, 16}
+      assert {:ok, [?*]} == WasmexWasmtime.call_function(instance, :fill_and_read, [])
+      assert_receive {:allocated, 20}
+    end
+
+    test "calls through a caller can not be given a timeout" do
+      test_pid = self()
+
+      imports = %{
+        env: %{
+          fill:
+            {:fn, [:i32], [:i32],
+             fn %{caller: caller, instance: instance}, _size ->
+               result =
+                 WasmexWasmtime.Instance.call_exported_function(
+                   caller,
+                   instance,
+                   "alloc",
+                   [1],
+                   make_ref(),
+                   timeout: 100
+                 )
+
+               send(test_pid, {:result, result})
+               0
+             end},
+          log: {:fn, [:i32], [], fn _context, _pointer -> nil end}
+        }
+      }
+
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @reentrant_wat, imports: imports}})
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :fill_and_read, [])
+      assert_receive {:result, {:error, _reason}}
+    end
+
+    test "calls through a caller fail after its callback returned" do
+      test_pid = self()
+
+      imports = %{
+        env: %{
+          fill:
+            {:fn, [:i32], [:i32],
+             fn context, _size ->
+               send(test_pid, {:context, context})
+               0
+             end},
+          log: {:fn, [:i32], [], fn _context, _pointer -> nil end}
+        }
+      }
+
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @reentrant_wat, imports: imports}})
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :fill_and_read, [])
+      assert_receive {:context, context}
+
+      assert {:error, _reason} = WasmexWasmtime.call_function_from_callback(context, :alloc, [1])
+    end
+
+    test "calls fail in callbacks handled without an instance" do
+      test_pid = self()
+
+      imports = %{
+        env: %{
+          fill:
+            {:fn, [:i32], [:i32],
+             fn context, _size ->
+               result = WasmexWasmtime.call_function_from_callback(context, :alloc, [1])
+               send(test_pid, {:result, result, Map.keys(context)})
+               0
+             end},
+          log: {:fn, [:i32], [], fn _context, _pointer -> nil end}
+        }
+      }
+
+      worker =
+        spawn_link(fn ->
+          receive do
+            message -> :ok = WasmexWasmtime.handle_callback(message, imports)
+          end
+        end)
+
+      opts = %{bytes: @reentrant_wat, imports: imports, callback_pid: worker}
+      instance = start_supervised!({WasmexWasmtime, opts})
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :fill_and_read, [])
+      assert_receive {:result, {:error, _reason}, context_keys}
+      refute :__callback_state__ in context_keys
+    end
+
+    test "a caller can not be used after its callback returned" do
+      test_pid = self()
+
+      imports = %{
+        env: %{
+          fill:
+            {:fn, [:i32], [:i32],
+             fn context, _size ->
+               send(test_pid, {:context, context})
+               0
+             end},
+          log: {:fn, [:i32], [], fn _context, _pointer -> nil end}
+        }
+      }
+
+      instance = start_supervised!({WasmexWasmtime, %{bytes: @reentrant_wat, imports: imports}})
+      assert {:ok, [0]} == WasmexWasmtime.call_function(instance, :fill_and_read, [])
+      assert_receive {:context, %{caller: caller, memory: memory}}
+
+      assert {:error, reason} = WasmexWasmtime.Memory.get_byte(caller, memory, 0)
+      assert reason =~ "the callback of this caller already returned"
+      assert {:error, _reason} = WasmexWasmtime.StoreOrCaller.fuel_consumed(caller)
+    end
+  end
+
   describe "when instantiating with imports" do
     def create_instance_with_atom_imports(_context) do
       imports = %{
@@ -216,6 +437,165 @@ defmodule WasmexWasmtimeTest do
     end
   end
 
+  describe "routing callbacks to other processes" do
+    defp serve_callbacks(imports, test_pid) do
+      receive do
+        {:invoke_callback, namespace_name, import_name, _context, _params, _token} = message ->
+          :ok = WasmexWasmtime.handle_callback(message, imports)
+          send(test_pid, {:handled_callback, self(), namespace_name, import_name})
+          serve_callbacks(imports, test_pid)
+      end
+    end
+
+    test "callbacks are sent to the callback_pid of the instance" do
+      imports = %{env: TestHelper.default_imported_functions_env()}
+      test_pid = self()
+      worker = spawn_link(fn -> serve_callbacks(imports, test_pid) end)
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+
+      opts = %{store: store, module: module, imports: imports, callback_pid: worker}
+      instance = start_supervised!({WasmexWasmtime, opts})
+
+      assert {:ok, [6]} == WasmexWasmtime.call_function(instance, :using_imported_sum3, [1, 2, 3])
+      assert_receive {:handled_callback, ^worker, "env", "imported_sum3"}
+    end
+
+    test "callbacks are sent to the process registered for the import" do
+      env = TestHelper.default_imported_functions_env()
+      test_pid = self()
+      worker = spawn_link(fn -> serve_callbacks(%{env: env}, test_pid) end)
+      Process.register(worker, :sum3_callback_worker)
+      {:fn, params, results, sum3} = env.imported_sum3
+      sum3 = {:fn, params, results, sum3, :sum3_callback_worker}
+      imports = %{env: %{env | imported_sum3: sum3}}
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+
+      instance =
+        start_supervised!({WasmexWasmtime, %{store: store, module: module, imports: imports}})
+
+      assert {:ok, [6]} == WasmexWasmtime.call_function(instance, :using_imported_sum3, [1, 2, 3])
+      assert_receive {:handled_callback, ^worker, "env", "imported_sum3"}
+
+      assert {:ok, []} == WasmexWasmtime.call_function(instance, :using_imported_void, [])
+      refute_receive {:handled_callback, _, "env", "imported_void"}
+    end
+
+    test "callbacks reach a restarted process registered under the same name" do
+      imports = %{env: TestHelper.default_imported_functions_env()}
+      test_pid = self()
+      worker = spawn(fn -> serve_callbacks(imports, test_pid) end)
+      Process.register(worker, :restarted_callback_worker)
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+
+      opts = %{
+        store: store,
+        module: module,
+        imports: imports,
+        callback_pid: :restarted_callback_worker
+      }
+
+      instance = start_supervised!({WasmexWasmtime, opts})
+      assert {:ok, [6]} == WasmexWasmtime.call_function(instance, :using_imported_sum3, [1, 2, 3])
+      assert_receive {:handled_callback, ^worker, "env", "imported_sum3"}
+
+      ref = Process.monitor(worker)
+      Process.exit(worker, :kill)
+      assert_receive {:DOWN, ^ref, :process, ^worker, :killed}
+      restarted = spawn_link(fn -> serve_callbacks(imports, test_pid) end)
+      Process.register(restarted, :restarted_callback_worker)
+
+      assert {:ok, [6]} == WasmexWasmtime.call_function(instance, :using_imported_sum3, [1, 2, 3])
+      assert_receive {:handled_callback, ^restarted, "env", "imported_sum3"}
+    end
+
+    test "callback forwarders stop when the instance stops" do
+      imports = %{env: TestHelper.default_imported_functions_env()}
+      worker = spawn_link(fn -> serve_callbacks(imports, self()) end)
+      Process.register(worker, :stopped_callback_worker)
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+
+      opts = %{
+        store: store,
+        module: module,
+        imports: imports,
+        callback_pid: :stopped_callback_worker
+      }
+
+      {:ok, instance} = WasmexWasmtime.start_link(opts)
+      %{instance: %{callback_forwarders: [forwarder]}} = :sys.get_state(instance)
+      ref = Process.monitor(forwarder)
+
+      :ok = GenServer.stop(instance)
+      assert_receive {:DOWN, ^ref, :process, ^forwarder, _reason}
+    end
+
+    test "callback forwarders of an instance can be stopped" do
+      imports = %{env: TestHelper.default_imported_functions_env()}
+      worker = spawn_link(fn -> serve_callbacks(imports, self()) end)
+      Process.register(worker, :unused_callback_worker)
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+
+      {:ok, instance} =
+        WasmexWasmtime.Instance.new(store, module, imports, callback_pid: :unused_callback_worker)
+
+      %WasmexWasmtime.Instance{callback_forwarders: [forwarder]} = instance
+      ref = Process.monitor(forwarder)
+
+      :ok = WasmexWasmtime.Instance.stop_callback_forwarders(instance)
+      assert_receive {:DOWN, ^ref, :process, ^forwarder, :killed}
+    end
+
+    test "instantiating with an unregistered name returns an error" do
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+      imports = %{env: TestHelper.default_imported_functions_env()}
+
+      assert {:error, reason} =
+               WasmexWasmtime.Instance.new(store, module, imports,
+                 callback_pid: :unregistered_callback_worker
+               )
+
+      assert reason =~ "unregistered_callback_worker"
+    end
+  end
+
+  describe "when instantiating with imports that trap" do
+    test "call_function returns the trap reason" do
+      imports = %{
+        env:
+          Map.merge(TestHelper.default_imported_functions_env(), %{
+            imported_sum3:
+              {:fn, [:i32, :i32, :i32], [:i32],
+               fn _context, _a, _b, _c -> {:trap, "permission denied"} end}
+          })
+      }
+
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+      instance =
+        start_supervised!({WasmexWasmtime, %{store: store, module: module, imports: imports}})
+
+      assert {:error, {:trap, "permission denied"}} ==
+               WasmexWasmtime.call_function(instance, :using_imported_sum3, [1, 2, 3])
+
+      assert {:ok, []} == WasmexWasmtime.call_function(instance, :using_imported_void, [])
+    end
+
+    test "call_function returns the exit code" do
+      imports = %{
+        env:
+          Map.merge(TestHelper.default_imported_functions_env(), %{
+            imported_void: {:fn, [], [], fn _context -> {:exit, 3} end}
+          })
+      }
+
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+      instance =
+        start_supervised!({WasmexWasmtime, %{store: store, module: module, imports: imports}})
+
+      assert {:error, {:exit, 3}} ==
+               WasmexWasmtime.call_function(instance, :using_imported_void, [])
+    end
+  end
+
   describe "when instantiating with imports that raise exceptions" do
     def create_instance_with_imports_raising_exceptions(_context) do
       imports = %{
@@ -240,11 +620,24 @@ defmodule WasmexWasmtimeTest do
     test "call_function using_imported_sum3 with both, string and atom, identifiers", %{
       instance: instance
     } do
-      assert {:error, reason} =
+      assert {:error, {:callback_error, error}} =
                WasmexWasmtime.call_function(instance, "using_imported_sum3", [1, 2, 3])
 
-      assert reason =~
-               "Error during function excecution: `error while executing at wasm backtrace:\n    0:  0x12e - <unknown>!using_imported_sum3"
+      assert %{kind: :error, message: "** (RuntimeError) oops", stacktrace: stacktrace} = error
+      assert stacktrace =~ "test/wasmex_wasmtime_test.exs"
+    end
+
+    test "call_function using_imported_void with a throwing callback" do
+      imports = %{env: %{imported_void: {:fn, [], [], fn _context -> throw(:oops) end}}}
+      %{store: store, module: module} = TestHelper.wasm_import_module()
+
+      instance =
+        start_supervised!({WasmexWasmtime, %{store: store, module: module, imports: imports}},
+          id: :throwing
+        )
+
+      assert {:error, {:callback_error, %{kind: :throw, message: "** (throw) :oops"}}} =
+               WasmexWasmtime.call_function(instance, "using_imported_void", [])
     end
   end
 end